@@ -1,12 +1,16 @@
 use eframe::{egui, App, Frame};
-use crate::state::MyApp;
+use crate::recovery;
+use crate::settings;
+use crate::state::{MyApp, PendingAction};
+use crate::variables::VariableFormat;
 use regex::Regex;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 impl App for MyApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut Frame) {
-        self.check_for_conversion_result();
-        self.check_for_import_result();
+        self.check_job_results(ctx);
+        self.autosave_if_needed(ctx);
+        self.check_external_changes(ctx);
 
         // 检查是否有文件拖入
         if !ctx.input(|i| i.raw.dropped_files.is_empty()) {
@@ -66,6 +70,32 @@ impl App for MyApp {
             }
         }
 
+        if ctx.input(|i| i.key_pressed(egui::Key::R) && i.modifiers.ctrl && i.modifiers.shift) {
+            ctx.input_mut(|i| i.consume_key(egui::Modifiers::CTRL | egui::Modifiers::SHIFT, egui::Key::R));
+            self.open_citation_picker();
+        }
+
+        if ctx.input(|i| i.key_pressed(egui::Key::S) && i.modifiers.ctrl && !i.modifiers.shift) {
+            ctx.input_mut(|i| i.consume_key(egui::Modifiers::CTRL, egui::Key::S));
+            self.save_file();
+        }
+
+        self.handle_tab_shortcuts(ctx);
+
+        // 关闭窗口前检查未保存的修改
+        if ctx.input(|i| i.viewport().close_requested())
+            && self.documents.iter().any(|d| d.is_dirty)
+            && !self.allow_close {
+            ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            self.request_action(ctx, PendingAction::Close);
+        }
+
+        let title = self.compose_window_title();
+        if title != self.window_title {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.clone()));
+            self.window_title = title;
+        }
+
         self.show_menu_bar(ctx);
         
         if self.about_window_open {
@@ -80,30 +110,135 @@ impl App for MyApp {
             self.show_style_palette(ctx);
         }
 
+        if self.citation_picker_open {
+            self.show_citation_picker(ctx);
+        }
+
         if self.info_dialog_open {
             self.show_info_dialog(ctx);
         }
 
+        if self.export_dialog_open {
+            self.show_export_dialog(ctx);
+        }
+
+        if self.filter_manager_open {
+            self.show_filter_manager(ctx);
+        }
+
+        if self.batch_export_dialog_open {
+            self.show_batch_export_dialog(ctx);
+        }
+
+        if self.import_options_dialog_open {
+            self.show_import_options_dialog(ctx);
+        }
+
         if self.import_dialog_open {
             self.show_import_dialog(ctx);
         }
+
+        if self.unsaved_dialog_open {
+            self.show_unsaved_changes_dialog(ctx);
+        }
+
+        if self.merge_dialog_open {
+            self.show_merge_dialog(ctx);
+        }
+
+        if self.split_dialog_open {
+            self.show_split_dialog(ctx);
+        }
+
+        if self.recovery_dialog_open {
+            self.show_recovery_dialog(ctx);
+        }
+
+        if self.documents.iter().any(|d| d.changed_on_disk) {
+            self.show_external_change_dialog(ctx);
+        }
         
+        if self.project_panel_open && self.project.is_some() {
+            self.show_project_panel(ctx);
+        }
+
+        if self.metadata_panel_open {
+            self.show_metadata_panel(ctx);
+        }
+
+        self.show_status_bar(ctx);
+        if self.log_panel_open {
+            self.show_log_panel(ctx);
+        }
         self.show_panels(ctx);
     }
+
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        self.autosave_project();
+        eframe::set_value(storage, settings::SETTINGS_KEY, &self.collect_settings());
+    }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        // 正常退出，下次启动时不再提示恢复
+        recovery::end_session();
+        // 不留下仍在运行的 pandoc 进程
+        self.jobs.cancel_all();
+    }
 }
 
 impl MyApp {
+    /// 处理标签页相关的快捷键。需要在编辑器渲染之前调用，避免 Tab 键被插入到文本中。
+    fn handle_tab_shortcuts(&mut self, ctx: &egui::Context) {
+        if ctx.input(|i| i.key_pressed(egui::Key::N) && i.modifiers.ctrl) {
+            ctx.input_mut(|i| i.consume_key(egui::Modifiers::CTRL, egui::Key::N));
+            self.new_document();
+        }
+
+        if ctx.input(|i| i.key_pressed(egui::Key::W) && i.modifiers.ctrl) {
+            ctx.input_mut(|i| i.consume_key(egui::Modifiers::CTRL, egui::Key::W));
+            let id = self.doc().id;
+            self.request_action(ctx, PendingAction::CloseDocument(id));
+        }
+
+        if ctx.input(|i| i.key_pressed(egui::Key::Tab) && i.modifiers.ctrl) {
+            let backwards = ctx.input(|i| i.modifiers.shift);
+            ctx.input_mut(|i| i.consume_key(egui::Modifiers::CTRL, egui::Key::Tab));
+            self.cycle_document(if backwards { -1 } else { 1 });
+        }
+
+        if ctx.input(|i| i.key_pressed(egui::Key::PageUp) && i.modifiers.ctrl) {
+            let reorder = ctx.input(|i| i.modifiers.shift);
+            ctx.input_mut(|i| i.consume_key(egui::Modifiers::CTRL, egui::Key::PageUp));
+            if reorder {
+                self.move_active_document(-1);
+            } else {
+                self.cycle_document(-1);
+            }
+        }
+
+        if ctx.input(|i| i.key_pressed(egui::Key::PageDown) && i.modifiers.ctrl) {
+            let reorder = ctx.input(|i| i.modifiers.shift);
+            ctx.input_mut(|i| i.consume_key(egui::Modifiers::CTRL, egui::Key::PageDown));
+            if reorder {
+                self.move_active_document(1);
+            } else {
+                self.cycle_document(1);
+            }
+        }
+    }
+
     pub fn scan_and_update_markers(&mut self) {
-        let re = Regex::new(r"\{\{([^}]+?)\}\}").unwrap();
+        let re = marker_regex();
+        let doc = self.doc_mut();
         let mut current_markers = HashSet::new();
-        for mat in re.find_iter(&self.markdown_text) {
+        for mat in re.find_iter(&doc.markdown_text) {
             current_markers.insert(mat.as_str().to_string());
         }
 
         // Create a new map with only the current markers, preserving old values
         let mut new_marker_values = std::collections::HashMap::new();
         for marker in &current_markers {
-            if let Some(old_value) = self.marker_values.get(marker) {
+            if let Some(old_value) = doc.marker_values.get(marker) {
                 new_marker_values.insert(marker.clone(), old_value.clone());
             } else {
                 new_marker_values.insert(marker.clone(), String::new());
@@ -111,11 +246,11 @@ impl MyApp {
         }
         
         // Replace the old map with the new one
-        self.marker_values = new_marker_values;
+        doc.marker_values = new_marker_values;
 
         // Update the sorted list of markers for the UI
-        self.template_markers = current_markers.into_iter().collect();
-        self.template_markers.sort();
+        doc.template_markers = current_markers.into_iter().collect();
+        doc.template_markers.sort();
     }
 
     pub fn open_info_dialog(&mut self, title: &str, message: &str) {
@@ -124,38 +259,37 @@ impl MyApp {
         self.info_dialog_open = true;
     }
 
-    /// Parses a string of `key=value` pairs, updates the internal `marker_values`,
-    /// and then applies all variables to the markdown text.
-    pub fn import_and_apply_variables(&mut self, text: &str) {
+    /// Parses pasted variables in the given format and applies them.
+    /// Returns the parse error without touching the document.
+    pub fn import_and_apply_variables(&mut self, text: &str, format: VariableFormat) -> Result<(), String> {
+        let assignments = format.parse(text)
+            .map_err(|e| format!("无法解析{}格式的变量：{}", format.label(), e))?;
+        self.apply_imported_variables(assignments);
+        Ok(())
+    }
+
+    /// Updates the internal `marker_values` from `({{key}}, value)` pairs,
+    /// and then applies all variables to the markdown text (unless markers are kept).
+    pub fn apply_imported_variables(&mut self, assignments: Vec<(String, String)>) {
         // First, get a clean state of markers from the document
         self.scan_and_update_markers();
 
         let mut updated_count = 0;
 
-        for line in text.lines() {
-            if line.trim().is_empty() {
-                continue;
-            }
-
-            if let Some((key, value)) = line.split_once('=') {
-                let key = key.trim();
-                let value = value.trim();
-
-                let full_marker = if key.starts_with("{{") && key.ends_with("}}") {
-                    key.to_string()
-                } else {
-                    format!("{{{{{}}}}}", key)
-                };
-
-                // ONLY update if the marker is currently in the document
-                if self.marker_values.contains_key(&full_marker) {
-                    self.marker_values.insert(full_marker, value.to_string());
-                    updated_count += 1;
-                }
+        for (full_marker, value) in assignments {
+            // ONLY update if the marker is currently in the document
+            if let Some(existing) = self.doc_mut().marker_values.get_mut(&full_marker) {
+                *existing = value;
+                updated_count += 1;
             }
         }
 
-        if updated_count > 0 {
+        if updated_count > 0 && self.keep_template_markers {
+            self.open_info_dialog(
+                "导入完成",
+                &format!("成功更新了 {} 个变量，预览和导出时将使用这些值。", updated_count)
+            );
+        } else if updated_count > 0 {
             self.apply_template_variables_to_markdown();
             self.open_info_dialog(
                 "导入完成",
@@ -171,16 +305,27 @@ impl MyApp {
 
     /// Replaces all placeholders in the markdown text with their corresponding values.
     pub fn apply_template_variables_to_markdown(&mut self) {
-        for (marker, value) in self.marker_values.clone() {
-            if !value.is_empty() {
-                self.markdown_text = self.markdown_text.replace(&marker, &value);
-            }
+        let doc = self.doc_mut();
+        let substituted = substitute_markers(&doc.markdown_text, &doc.marker_values);
+        if substituted != doc.markdown_text {
+            doc.markdown_text = substituted;
+            doc.is_dirty = true;
+        }
+    }
+
+    /// In keep-markers mode, fills the assigned values into a copy of the active
+    /// document's text for preview or export; otherwise returns the text unchanged.
+    pub fn substitute_document_variables(&self, text: String) -> String {
+        if self.keep_template_markers {
+            substitute_markers(&text, &self.doc().marker_values)
+        } else {
+            text
         }
     }
 
     pub fn apply_underline_to_variables(&mut self, ctx: &egui::Context) {
         let mut replacements = Vec::new();
-        let markdown_clone = self.markdown_text.clone();
+        let markdown_clone = self.doc().markdown_text.clone();
 
         for mat in self.underline_regex.find_iter(&markdown_clone) {
             let start = mat.start();
@@ -190,7 +335,7 @@ impl MyApp {
                 .and_then(|s| s.chars().last()) == Some('[');
             
             let is_followed = markdown_clone.get(end..)
-                .map_or(false, |s| s.starts_with("]{.underline}"));
+                .is_some_and(|s| s.starts_with("]{.underline}"));
 
             if !is_preceded || !is_followed {
                 replacements.push((mat.range(), format!("[{}]{{.underline}}", mat.as_str())));
@@ -200,9 +345,11 @@ impl MyApp {
         let count = replacements.len();
 
         if count > 0 {
+            let doc = self.doc_mut();
             for (range, replacement) in replacements.iter().rev() {
-                self.markdown_text.replace_range(range.clone(), replacement);
+                doc.markdown_text.replace_range(range.clone(), replacement);
             }
+            doc.is_dirty = true;
             self.info_dialog_message = format!("成功为 {} 个占位符添加了下划线。", count);
         } else {
             self.info_dialog_message = "未找到需要添加下划线的 {{...}} 标记。".to_string();
@@ -213,3 +360,61 @@ impl MyApp {
         ctx.request_repaint();
     }
 }
+
+/// Parses `key=value` lines into `({{key}}, value)` pairs.
+/// Keys may be written with or without the surrounding `{{ }}`.
+pub fn parse_variable_assignments(text: &str) -> Vec<(String, String)> {
+    let mut assignments = Vec::new();
+
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if let Some((key, value)) = line.split_once('=') {
+            let key = key.trim();
+            let value = value.trim();
+
+            assignments.push((marker_for_key(key), value.to_string()));
+        }
+    }
+
+    assignments
+}
+
+/// Matches `{{marker}}` template variables.
+fn marker_regex() -> Regex {
+    // `{{> 文件}}` 是包含指令，不是模板变量
+    Regex::new(r"\{\{([^>}][^}]*?)\}\}").unwrap()
+}
+
+/// Replaces each `{{marker}}` in `text` with its value. Empty values leave the
+/// marker in place so unassigned variables stay visible. Only markers in the
+/// original text are replaced, so a value that itself contains `{{...}}` is
+/// inserted as is regardless of the order of `values`.
+pub fn substitute_markers<'a>(text: &str, values: impl IntoIterator<Item = (&'a String, &'a String)>) -> String {
+    let values: HashMap<&str, &str> = values
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(marker, value)| (marker.as_str(), value.as_str()))
+        .collect();
+    if values.is_empty() {
+        return text.to_string();
+    }
+    marker_regex()
+        .replace_all(text, |caps: &regex::Captures| {
+            let marker = &caps[0];
+            values.get(marker).copied().unwrap_or(marker).to_string()
+        })
+        .into_owned()
+}
+
+/// Wraps a variable name in `{{ }}` unless it already is a full marker.
+pub fn marker_for_key(key: &str) -> String {
+    let key = key.trim();
+    if key.starts_with("{{") && key.ends_with("}}") {
+        key.to_string()
+    } else {
+        format!("{{{{{}}}}}", key)
+    }
+}
@@ -0,0 +1,412 @@
+use crate::app::{marker_for_key, substitute_markers};
+use crate::export::ExportFormat;
+use crate::state::MyApp;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// 可以作为批量导出数据表的文件扩展名
+pub const TABLE_EXTENSIONS: [&str; 4] = ["csv", "tsv", "txt", "json"];
+
+/// 文件名中不允许出现的字符（按 Windows 的规则，其他系统同样适用）
+const INVALID_FILE_NAME_CHARS: [char; 9] = ['\\', '/', ':', '*', '?', '"', '<', '>', '|'];
+
+/// 批量导出使用的数据表：每一列对应一个模板变量，每一行生成一份文档
+#[derive(Debug, Clone, Default)]
+pub struct VariableTable {
+    /// `{{名称}}` 形式的变量标记
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl VariableTable {
+    /// 按扩展名读取 CSV、TSV 或 JSON 文件。CSV/TSV 的第一行是变量名；
+    /// JSON 是对象组成的数组，每个对象的字段对应变量。
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let bytes = std::fs::read(path)
+            .map_err(|e| format!("无法读取数据表 {}：{}", path.display(), e))?;
+        let text = String::from_utf8(bytes)
+            .map_err(|_| format!("数据表 {} 不是 UTF-8 编码，请在 Excel 中另存为“CSV UTF-8”后再试。", path.display()))?;
+        let text = text.strip_prefix('\u{feff}').unwrap_or(&text);
+
+        let extension = path.extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let table = match extension.as_str() {
+            "json" => Self::from_json(text),
+            "csv" => Self::from_records(parse_delimited(text, ',')),
+            _ => Self::from_records(parse_delimited(text, '\t')),
+        }
+        .map_err(|e| format!("无法解析数据表 {}：{}", path.display(), e))?;
+
+        if table.rows.is_empty() {
+            return Err(format!("数据表 {} 中没有任何数据行。", path.display()));
+        }
+        Ok(table)
+    }
+
+    /// 第一条记录是表头，其余每条记录是一行数据。
+    /// Excel 等软件可能在每行末尾多写一个分隔符，末尾空白的字段会被忽略。
+    fn from_records(records: Vec<Vec<String>>) -> Result<Self, String> {
+        let mut records = records.into_iter();
+        let Some(mut header) = records.next() else {
+            return Err("文件是空的。".to_string());
+        };
+        trim_blank_fields(&mut header);
+        let columns = header_markers(header)?;
+
+        let mut rows = Vec::new();
+        for (index, mut row) in records.enumerate() {
+            if row.len() > columns.len() && row[columns.len()..].iter().all(|field| field.trim().is_empty()) {
+                row.truncate(columns.len());
+            }
+            if row.len() > columns.len() {
+                return Err(format!(
+                    "第 {} 行数据有 {} 列，但表头只有 {} 列。",
+                    index + 1,
+                    row.len(),
+                    columns.len()
+                ));
+            }
+            row.resize(columns.len(), String::new());
+            rows.push(row);
+        }
+        Ok(VariableTable { columns, rows })
+    }
+
+    fn from_json(text: &str) -> Result<Self, String> {
+        let value: serde_json::Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        let Some(items) = value.as_array() else {
+            return Err("JSON 数据表必须是由对象组成的数组。".to_string());
+        };
+
+        let mut keys: Vec<String> = Vec::new();
+        for item in items {
+            let Some(object) = item.as_object() else {
+                return Err("JSON 数据表必须是由对象组成的数组。".to_string());
+            };
+            for key in object.keys() {
+                if !keys.contains(key) {
+                    keys.push(key.clone());
+                }
+            }
+        }
+        if keys.is_empty() {
+            return Err("JSON 数据表中的对象没有任何字段。".to_string());
+        }
+
+        let rows = items.iter()
+            .filter_map(|item| item.as_object())
+            .map(|object| {
+                keys.iter()
+                    .map(|key| match object.get(key) {
+                        None | Some(serde_json::Value::Null) => String::new(),
+                        Some(serde_json::Value::String(text)) => text.clone(),
+                        Some(other) => other.to_string(),
+                    })
+                    .collect()
+            })
+            .collect();
+        Ok(VariableTable { columns: header_markers(keys)?, rows })
+    }
+
+    /// 用一行数据替换文本中的变量；空白的值不替换，与“模板赋值”的行为一致
+    pub fn substitute(&self, text: &str, row: &[String]) -> String {
+        substitute_markers(text, self.columns.iter().zip(row))
+    }
+
+    /// 按文件名模板为每一行生成输出文件名。某一行的变量为空时用行号代替，
+    /// 文件名中的非法字符替换为 `_`，缺少扩展名时补上，重名的文件依次加上 `-2`、`-3` 等后缀。
+    pub fn output_file_names(&self, pattern: &str, format: ExportFormat) -> Vec<String> {
+        let extension = format!(".{}", format.extension());
+        let mut used = HashSet::new();
+        self.rows.iter()
+            .enumerate()
+            .map(|(index, row)| {
+                let row_number = (index + 1).to_string();
+                let values = row.iter().map(|value| if value.trim().is_empty() { &row_number } else { value });
+                let filled = substitute_markers(pattern.trim(), self.columns.iter().zip(values));
+                let split = filled.len().saturating_sub(extension.len());
+                let stem = match filled.get(split..) {
+                    Some(suffix) if suffix.eq_ignore_ascii_case(&extension) => &filled[..split],
+                    _ => filled.as_str(),
+                };
+                let stem = sanitize_file_stem(stem);
+
+                let mut name = format!("{}{}", stem, extension);
+                let mut counter = 2;
+                while !used.insert(name.to_lowercase()) {
+                    name = format!("{}-{}{}", stem, counter, extension);
+                    counter += 1;
+                }
+                name
+            })
+            .collect()
+    }
+}
+
+/// 将表头中的名称转换为 `{{名称}}` 标记，空白或重复的名称视为错误
+fn header_markers(header: Vec<String>) -> Result<Vec<String>, String> {
+    let mut markers: Vec<String> = Vec::new();
+    for (index, name) in header.iter().enumerate() {
+        if name.trim().is_empty() {
+            return Err(format!("第 {} 列没有变量名。", index + 1));
+        }
+        let marker = marker_for_key(name);
+        if markers.contains(&marker) {
+            return Err(format!("变量 {} 出现了不止一次。", marker));
+        }
+        markers.push(marker);
+    }
+    Ok(markers)
+}
+
+/// 去掉记录末尾的空白字段
+fn trim_blank_fields(record: &mut Vec<String>) {
+    while record.last().is_some_and(|field| field.trim().is_empty()) {
+        record.pop();
+    }
+}
+
+/// 解析 CSV/TSV 文本。支持用双引号包围含有分隔符或换行的字段，`""` 表示一个双引号；
+/// 完全空白的行被忽略。
+pub fn parse_delimited(text: &str, delimiter: char) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => in_quotes = true,
+            c if c == delimiter => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' | '\r' => {
+                record.push(std::mem::take(&mut field));
+                push_record(&mut records, std::mem::take(&mut record));
+            }
+            _ => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        push_record(&mut records, record);
+    }
+    records
+}
+
+fn push_record(records: &mut Vec<Vec<String>>, record: Vec<String>) {
+    if record.iter().any(|field| !field.trim().is_empty()) {
+        records.push(record);
+    }
+}
+
+/// 去掉文件名中的非法字符和首尾的空格、句点
+fn sanitize_file_stem(stem: &str) -> String {
+    let cleaned: String = stem
+        .chars()
+        .map(|c| if INVALID_FILE_NAME_CHARS.contains(&c) || c.is_control() { '_' } else { c })
+        .collect();
+    let cleaned = cleaned.trim().trim_end_matches('.').to_string();
+    if cleaned.is_empty() {
+        "未命名".to_string()
+    } else {
+        cleaned
+    }
+}
+
+/// 批量导出对话框的状态
+pub struct BatchExport {
+    pub table_path: Option<PathBuf>,
+    pub table: Option<VariableTable>,
+    pub output_dir: Option<PathBuf>,
+    /// 输出文件名模板，例如 `{{公司名称}}-投标书.docx`
+    pub file_name_pattern: String,
+    pub format: ExportFormat,
+}
+
+impl Default for BatchExport {
+    fn default() -> Self {
+        BatchExport {
+            table_path: None,
+            table: None,
+            output_dir: None,
+            file_name_pattern: String::new(),
+            format: ExportFormat::Docx,
+        }
+    }
+}
+
+impl MyApp {
+    /// 打开批量导出对话框，格式默认使用当前导出方案的格式
+    pub fn open_batch_export(&mut self) {
+        self.scan_and_update_markers();
+        self.batch_export.format = self.export_settings.active().format;
+        if self.batch_export.output_dir.is_none() {
+            self.batch_export.output_dir = self.doc().base_dir().map(Path::to_path_buf);
+        }
+        self.batch_export_dialog_open = true;
+    }
+
+    /// 选择数据表文件并读取
+    pub fn choose_batch_table(&mut self) {
+        let Some(path) = self.file_dialog()
+            .add_filter("数据表 (CSV/TSV/JSON)", &TABLE_EXTENSIONS)
+            .pick_file() else {
+            return;
+        };
+        self.remember_directory(&path);
+
+        match VariableTable::load(&path) {
+            Ok(table) => {
+                // 文件名模板默认使用第一个变量加上文档名
+                if self.batch_export.file_name_pattern.trim().is_empty() {
+                    let stem = self.doc()
+                        .file_path
+                        .as_ref()
+                        .and_then(|p| p.file_stem())
+                        .map(|s| s.to_string_lossy().into_owned())
+                        .unwrap_or_else(|| "导出".to_string());
+                    self.batch_export.file_name_pattern = match table.columns.first() {
+                        Some(column) => format!("{}-{}", column, stem),
+                        None => stem,
+                    };
+                }
+                self.batch_export.table = Some(table);
+                self.batch_export.table_path = Some(path);
+            }
+            Err(error_message) => {
+                self.open_info_dialog("读取数据表失败", &error_message);
+            }
+        }
+    }
+
+    pub fn choose_batch_output_dir(&mut self) {
+        let mut dialog = rfd::FileDialog::new();
+        if let Some(dir) = self.batch_export.output_dir.as_ref().or(self.recent_directories.first()) {
+            dialog = dialog.set_directory(dir);
+        }
+        if let Some(dir) = dialog.pick_folder() {
+            self.batch_export.output_dir = Some(dir);
+        }
+    }
+
+    /// 为数据表的每一行替换变量并加入一个导出任务，编辑区中的文档保持不变
+    pub fn run_batch_export(&mut self) {
+        let (Some(table), Some(output_dir)) = (self.batch_export.table.clone(), self.batch_export.output_dir.clone()) else {
+            return;
+        };
+        let Some((markdown_content, resource_dirs)) = self.prepare_document_export() else {
+            return;
+        };
+
+        let format = self.batch_export.format;
+        let file_names = table.output_file_names(&self.batch_export.file_name_pattern, format);
+        let existing: Vec<&String> = file_names.iter()
+            .filter(|name| output_dir.join(name).exists())
+            .collect();
+        if !existing.is_empty() {
+            let mut listed: Vec<&str> = existing.iter().take(10).map(|name| name.as_str()).collect();
+            if existing.len() > listed.len() {
+                listed.push("……");
+            }
+            let confirmed = rfd::MessageDialog::new()
+                .set_level(rfd::MessageLevel::Warning)
+                .set_title("覆盖文件")
+                .set_description(format!(
+                    "输出文件夹中已有 {} 个同名文件，是否覆盖？\n\n{}",
+                    existing.len(),
+                    listed.join("\n")
+                ))
+                .set_buttons(rfd::MessageButtons::YesNo)
+                .show();
+            if confirmed != rfd::MessageDialogResult::Yes {
+                return;
+            }
+        }
+
+        let mut profile = self.export_settings.active().clone();
+        profile.format = format;
+        let source_document = Some(self.doc().id);
+        self.batch_export_dialog_open = false;
+        for (row, file_name) in table.rows.iter().zip(file_names) {
+            // 数据表中的值优先，表中为空的变量再使用“模板赋值”中的值
+            let content = self.substitute_document_variables(table.substitute(&markdown_content, row));
+            // 导出前的检查失败时每一行都会失败，不再继续
+            if !self.queue_export(content, output_dir.join(file_name), &profile, resource_dirs.clone(), source_document) {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(fields: &[&str]) -> Vec<String> {
+        fields.iter().map(|field| field.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_quotes_and_line_breaks() {
+        let records = parse_delimited("名称,地址\r\n\"甲, 乙\",\"第一行\n第二行\"\r\n\r\n\"说\"\"好\"\"\",x", ',');
+        assert_eq!(records, vec![
+            strings(&["名称", "地址"]),
+            strings(&["甲, 乙", "第一行\n第二行"]),
+            strings(&["说\"好\"", "x"]),
+        ]);
+    }
+
+    #[test]
+    fn parses_tab_separated_text() {
+        assert_eq!(parse_delimited("a\tb\n1\t\n", '\t'), vec![strings(&["a", "b"]), strings(&["1", ""])]);
+    }
+
+    #[test]
+    fn ignores_trailing_blank_fields() {
+        let table = VariableTable::from_records(parse_delimited("a,b,\n1,2,\n3\n", ',')).unwrap();
+        assert_eq!(table.columns, strings(&["{{a}}", "{{b}}"]));
+        assert_eq!(table.rows, vec![strings(&["1", "2"]), strings(&["3", ""])]);
+        assert!(VariableTable::from_records(parse_delimited("a\n1,2\n", ',')).is_err());
+    }
+
+    #[test]
+    fn rejects_json_objects_without_fields() {
+        assert!(VariableTable::from_json("[{}]").is_err());
+        let table = VariableTable::from_json("[{\"公司\": \"甲\", \"编号\": 1}, {\"公司\": null}]").unwrap();
+        assert_eq!(table.columns, strings(&["{{公司}}", "{{编号}}"]));
+        assert_eq!(table.rows, vec![strings(&["甲", "1"]), strings(&["", ""])]);
+    }
+
+    #[test]
+    fn output_file_names_are_unique_and_valid() {
+        let table = VariableTable {
+            columns: strings(&["{{公司}}", "{{编号}}"]),
+            rows: vec![
+                strings(&["甲/乙", "1"]),
+                strings(&["甲/乙", "2"]),
+                strings(&["", "3"]),
+            ],
+        };
+        assert_eq!(
+            table.output_file_names("{{公司}}-投标书.DOCX", ExportFormat::Docx),
+            strings(&["甲_乙-投标书.docx", "甲_乙-投标书-2.docx", "3-投标书.docx"])
+        );
+        assert_eq!(
+            table.output_file_names("{{编号}}", ExportFormat::Pdf),
+            strings(&["1.pdf", "2.pdf", "3.pdf"])
+        );
+    }
+}
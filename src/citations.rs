@@ -0,0 +1,254 @@
+use crate::metadata;
+use crate::watcher;
+use regex::Regex;
+use serde_yaml_ng::Value;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// 多长时间检查一次参考文献文件是否被修改
+const BIBLIOGRAPHY_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// 可以作为参考文献的文件扩展名：BibTeX、CSL JSON 和 CSL YAML
+pub const BIBLIOGRAPHY_EXTENSIONS: [&str; 5] = ["bib", "bibtex", "json", "yaml", "yml"];
+
+/// 参考文献中的一条记录
+#[derive(Debug, Clone)]
+pub struct BibEntry {
+    pub key: String,
+    pub title: String,
+}
+
+/// 按扩展名读取 BibTeX 或 CSL JSON/YAML 文件中的记录
+pub fn load_bibliography(path: &Path) -> Result<Vec<BibEntry>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("无法读取参考文献 {}：{}", path.display(), e))?;
+    let extension = path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "json" => {
+            let json: serde_json::Value = serde_json::from_str(&text)
+                .map_err(|e| format!("无法解析参考文献 {}：{}", path.display(), e))?;
+            let value = serde_yaml_ng::to_value(json)
+                .map_err(|e| format!("无法解析参考文献 {}：{}", path.display(), e))?;
+            Ok(csl_entries(&value))
+        }
+        "yaml" | "yml" => {
+            let value: Value = serde_yaml_ng::from_str(&text)
+                .map_err(|e| format!("无法解析参考文献 {}：{}", path.display(), e))?;
+            // CSL YAML 可以是记录列表，也可以放在 `references` 字段中
+            Ok(csl_entries(value.get("references").unwrap_or(&value)))
+        }
+        _ => Ok(parse_bibtex(&text)),
+    }
+}
+
+/// 读取 CSL 记录列表中的 `id` 和 `title`
+fn csl_entries(value: &Value) -> Vec<BibEntry> {
+    let Some(items) = value.as_sequence() else {
+        return Vec::new();
+    };
+    items.iter()
+        .filter_map(|item| {
+            let key = match item.get("id")? {
+                Value::String(id) => id.clone(),
+                Value::Number(id) => id.to_string(),
+                _ => return None,
+            };
+            let title = item.get("title").and_then(Value::as_str).unwrap_or_default().to_string();
+            Some(BibEntry { key, title })
+        })
+        .collect()
+}
+
+/// 从 BibTeX 文本中找出各条记录的键和标题
+fn parse_bibtex(text: &str) -> Vec<BibEntry> {
+    let entry_start = Regex::new(r"@(\w+)\s*[{(]\s*([^,\s]+)\s*,").unwrap();
+    let title_field = Regex::new(r"(?i)\btitle\s*=\s*").unwrap();
+
+    let starts: Vec<_> = entry_start.captures_iter(text).collect();
+    let mut entries = Vec::new();
+    for (index, caps) in starts.iter().enumerate() {
+        let entry_type = caps[1].to_lowercase();
+        if matches!(entry_type.as_str(), "comment" | "string" | "preamble") {
+            continue;
+        }
+        let body_start = caps.get(0).map_or(0, |m| m.end());
+        let body_end = starts.get(index + 1)
+            .and_then(|next| next.get(0))
+            .map_or(text.len(), |m| m.start());
+        let body = &text[body_start..body_end];
+        let title = title_field.find(body)
+            .map(|m| bibtex_value(&body[m.end()..]))
+            .unwrap_or_default();
+        entries.push(BibEntry { key: caps[2].to_string(), title });
+    }
+    entries
+}
+
+/// 读取 `{...}` 或 `"..."` 形式的 BibTeX 字段值，去掉大括号并合并空白
+fn bibtex_value(rest: &str) -> String {
+    let mut chars = rest.chars();
+    let mut value = String::new();
+    match chars.next() {
+        Some('{') => {
+            let mut depth = 1;
+            for c in chars {
+                match c {
+                    '{' => depth += 1,
+                    '}' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+                    _ => value.push(c),
+                }
+            }
+        }
+        Some('"') => {
+            for c in chars {
+                match c {
+                    '"' => break,
+                    '{' | '}' => {}
+                    _ => value.push(c),
+                }
+            }
+        }
+        _ => {}
+    }
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// 找出文档中引用的所有键（`[@key]`、`@key` 等），代码块中的内容不计入
+pub fn cited_keys(text: &str) -> Vec<String> {
+    let citation = Regex::new(r"(?:^|[^\w@.])-?@(\w[\w:.#$%&+?<>~/-]*)").unwrap();
+    let mut keys: Vec<String> = Vec::new();
+    let mut in_code_block = false;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if in_code_block || !line.contains('@') {
+            continue;
+        }
+        for caps in citation.captures_iter(line) {
+            // 句末的标点不属于引用键
+            let key = caps[1].trim_end_matches(|c: char| !c.is_alphanumeric() && c != '_');
+            if !keys.iter().any(|k| k == key) {
+                keys.push(key.to_string());
+            }
+        }
+    }
+    keys
+}
+
+/// 文档元数据中 `bibliography` 字段列出的文件，相对路径以 `base_dir` 为准
+fn declared_bibliographies(fields: Option<&serde_yaml_ng::Mapping>, base_dir: Option<&Path>) -> Vec<PathBuf> {
+    let paths: Vec<&str> = match fields.and_then(|fields| fields.get("bibliography")) {
+        Some(Value::String(path)) => vec![path.as_str()],
+        Some(Value::Sequence(items)) => items.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    paths.into_iter()
+        .map(|path| match base_dir {
+            Some(dir) => dir.join(path),
+            None => PathBuf::from(path),
+        })
+        .collect()
+}
+
+/// 文档可以引用的参考文献：元数据中声明的文件、导出方案中的文件和元数据中直接写的 `references`。
+/// 同时记录文档中引用了但参考文献中没有的键。
+pub struct CitationIndex {
+    sources: Vec<PathBuf>,
+    modified: Vec<Option<SystemTime>>,
+    file_entries: Vec<BibEntry>,
+    text_hash: u64,
+    checked_at: Instant,
+    pub entries: Vec<BibEntry>,
+    /// 无法读取或解析的参考文献文件
+    pub errors: Vec<String>,
+    /// 在参考文献中找不到的引用键
+    pub missing: Vec<String>,
+}
+
+impl CitationIndex {
+    /// 返回与文档当前内容一致的参考文献；文档和导出方案都没有声明参考文献时返回 None
+    pub fn refresh<'a>(
+        cache: &'a mut Option<CitationIndex>,
+        text: &str,
+        base_dir: Option<&Path>,
+        profile_bibliography: Option<&Path>,
+    ) -> Option<&'a CitationIndex> {
+        if profile_bibliography.is_none() && metadata::find_front_matter(text).is_none() {
+            *cache = None;
+            return None;
+        }
+
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        text.hash(&mut hasher);
+        base_dir.hash(&mut hasher);
+        profile_bibliography.hash(&mut hasher);
+        let text_hash = hasher.finish();
+
+        let stale = cache.as_ref().is_none_or(|index| {
+            index.text_hash != text_hash || index.checked_at.elapsed() > BIBLIOGRAPHY_CHECK_INTERVAL
+        });
+        if !stale {
+            return cache.as_ref();
+        }
+
+        let fields = metadata::front_matter_fields(text);
+        let mut sources = declared_bibliographies(fields.as_ref(), base_dir);
+        if let Some(path) = profile_bibliography {
+            sources.push(path.to_path_buf());
+        }
+        let inline_entries = fields.as_ref()
+            .and_then(|fields| fields.get("references"))
+            .map(csl_entries);
+        if sources.is_empty() && inline_entries.is_none() {
+            *cache = None;
+            return None;
+        }
+
+        let index = cache.get_or_insert_with(|| CitationIndex {
+            sources: Vec::new(),
+            modified: Vec::new(),
+            file_entries: Vec::new(),
+            text_hash: 0,
+            checked_at: Instant::now(),
+            entries: Vec::new(),
+            errors: Vec::new(),
+            missing: Vec::new(),
+        });
+
+        // 只有参考文献文件变化时才重新读取
+        let modified: Vec<_> = sources.iter().map(|path| watcher::modified_time(path)).collect();
+        if index.sources != sources || index.modified != modified || index.file_entries.is_empty() {
+            index.file_entries.clear();
+            index.errors.clear();
+            for path in &sources {
+                match load_bibliography(path) {
+                    Ok(entries) => index.file_entries.extend(entries),
+                    Err(error) => index.errors.push(error),
+                }
+            }
+            index.sources = sources;
+            index.modified = modified;
+        }
+
+        index.entries = index.file_entries.clone();
+        index.entries.extend(inline_entries.unwrap_or_default());
+        index.missing = cited_keys(text)
+            .into_iter()
+            .filter(|key| !index.entries.iter().any(|entry| &entry.key == key))
+            .collect();
+        index.text_hash = text_hash;
+        index.checked_at = Instant::now();
+        cache.as_ref()
+    }
+}
@@ -0,0 +1,88 @@
+use crate::citations::CitationIndex;
+use crate::preview::PreviewCache;
+use std::collections::HashMap;
+use crate::watcher;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// 一个打开的文档（标签页）及其编辑器状态
+pub struct Document {
+    /// 在本次运行中唯一的编号，用于区分各标签页的编辑器状态（光标、撤销历史）
+    pub id: u64,
+    pub markdown_text: String,
+    /// 文档在磁盘上的路径，未保存过的文档为 None
+    pub file_path: Option<PathBuf>,
+    /// 尚未保存的文档中相对路径（例如导入时提取的图片）所基于的目录
+    pub resource_dir: Option<PathBuf>,
+    /// 文档自上次打开或保存后是否被修改过
+    pub is_dirty: bool,
+    /// 最近一次读取或写入时文件在磁盘上的修改时间，用于发现外部修改
+    pub disk_modified: Option<SystemTime>,
+    /// 文件在外部被修改，而编辑器中也有未保存的修改，等待用户选择保留哪一份
+    pub changed_on_disk: bool,
+    pub template_markers: Vec<String>,
+    pub marker_values: HashMap<String, String>,
+    /// 预览区的渲染缓存
+    pub cache: egui_commonmark::CommonMarkCache,
+    /// 文档包含 `{{> 文件}}` 指令或相对路径的图片时，预览区使用的处理结果
+    pub preview_cache: Option<PreviewCache>,
+    /// 文档可以引用的参考文献，以及找不到的引用键
+    pub citation_index: Option<CitationIndex>,
+    pub scroll_proportion: f32,
+    pub preview_max_scroll: f32,
+    /// 下一帧要将编辑器光标移动到的字符位置，例如从日志面板跳转到某一行
+    pub pending_cursor: Option<usize>,
+    /// 由“合并文件”得到的文档中，各来源文件在合并时的内容哈希，
+    /// 拆分回原文件前用来发现合并之后在外部被修改过的文件
+    pub merged_sources: HashMap<PathBuf, u64>,
+}
+
+impl Document {
+    pub fn new(id: u64, markdown_text: String, file_path: Option<PathBuf>) -> Self {
+        Self {
+            id,
+            markdown_text,
+            disk_modified: file_path.as_deref().and_then(watcher::modified_time),
+            file_path,
+            resource_dir: None,
+            is_dirty: false,
+            changed_on_disk: false,
+            template_markers: Vec::new(),
+            marker_values: HashMap::new(),
+            cache: egui_commonmark::CommonMarkCache::default(),
+            preview_cache: None,
+            citation_index: None,
+            scroll_proportion: 0.0,
+            preview_max_scroll: 0.0,
+            pending_cursor: None,
+            merged_sources: HashMap::new(),
+        }
+    }
+
+    /// 标签页和窗口标题中显示的名称
+    pub fn display_name(&self) -> String {
+        self.file_path
+            .as_ref()
+            .and_then(|p| p.file_name())
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "未命名".to_string())
+    }
+
+    /// 该文档编辑器 TextEdit 的 id，每个标签页拥有独立的光标和撤销历史
+    pub fn editor_id(&self) -> egui::Id {
+        egui::Id::new("main_editor_id").with(self.id)
+    }
+
+    /// 文档中相对路径的图片等资源所基于的目录
+    pub fn base_dir(&self) -> Option<&Path> {
+        self.file_path
+            .as_deref()
+            .and_then(Path::parent)
+            .or(self.resource_dir.as_deref())
+    }
+
+    /// 未保存过、未修改的文档可以被新打开的文件直接替换
+    pub fn is_replaceable(&self) -> bool {
+        self.file_path.is_none() && !self.is_dirty
+    }
+}
@@ -0,0 +1,438 @@
+use crate::metadata;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// 导出对话框中可选的常用 PDF 引擎，也可以手动输入其他引擎
+pub const KNOWN_PDF_ENGINES: [&str; 7] = ["xelatex", "lualatex", "pdflatex", "tectonic", "wkhtmltopdf", "weasyprint", "typst"];
+
+/// 导出的目标格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ExportFormat {
+    #[default]
+    Docx,
+    Odt,
+    Html,
+    Epub,
+    Pdf,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 5] = [
+        ExportFormat::Docx,
+        ExportFormat::Odt,
+        ExportFormat::Html,
+        ExportFormat::Epub,
+        ExportFormat::Pdf,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExportFormat::Docx => "Word 文档 (DOCX)",
+            ExportFormat::Odt => "OpenDocument 文本 (ODT)",
+            ExportFormat::Html => "网页 (HTML)",
+            ExportFormat::Epub => "电子书 (EPUB)",
+            ExportFormat::Pdf => "PDF",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Docx => "docx",
+            ExportFormat::Odt => "odt",
+            ExportFormat::Html => "html",
+            ExportFormat::Epub => "epub",
+            ExportFormat::Pdf => "pdf",
+        }
+    }
+
+    /// 传给 pandoc `-t` 参数的输出格式；PDF 由 pandoc 根据 PDF 引擎决定中间格式
+    pub fn pandoc_writer(&self) -> Option<&'static str> {
+        match self {
+            ExportFormat::Docx => Some("docx"),
+            ExportFormat::Odt => Some("odt"),
+            ExportFormat::Html => Some("html5"),
+            ExportFormat::Epub => Some("epub3"),
+            ExportFormat::Pdf => None,
+        }
+    }
+}
+
+/// 独占一行时表示在此处插入目录的标记
+pub const TOC_MARKER: &str = "[TOC]";
+
+/// 导出对话框中可选的代码高亮样式，对应 pandoc 的 `--highlight-style`
+pub const HIGHLIGHT_STYLES: [&str; 8] = ["pygments", "tango", "espresso", "zenburn", "kate", "monochrome", "breezedark", "haddock"];
+
+/// 导出方案中的一个过滤器
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FilterEntry {
+    pub path: PathBuf,
+    /// 停用的过滤器保留在方案中，但导出时不使用
+    pub enabled: bool,
+}
+
+/// `.lua` 文件作为 Lua 过滤器，其他文件作为外部过滤器程序
+pub fn is_lua_filter(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("lua"))
+}
+
+/// 过滤器在界面中显示的名称
+pub fn filter_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+/// 处理引用的方式。pandoc 2.11 起内置 `--citeproc`，更早的版本需要单独安装的 pandoc-citeproc 过滤器。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Citeproc {
+    None,
+    Builtin,
+    Filter,
+}
+
+/// 一组命名的导出选项。不同的客户或用途可以各自保存一套格式、模板和 pandoc 参数。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportProfile {
+    pub name: String,
+    pub format: ExportFormat,
+    /// 导出 DOCX 或 ODT 时使用的参考文档；DOCX 未设置时使用“设置导出模板”选择的模板
+    pub reference_doc: Option<PathBuf>,
+    /// 导出 HTML 时引用的 CSS 样式表
+    pub html_css: Option<PathBuf>,
+    /// 导出 HTML 时将图片和样式嵌入到单个文件中
+    pub html_embed_resources: bool,
+    pub pdf_engine: String,
+    /// 使用 LaTeX 引擎导出 PDF 时的中文字体，为空时使用引擎默认字体
+    pub pdf_cjk_font: String,
+    /// 在文档开头生成目录；文档中有 `[TOC]` 标记时总是生成目录
+    pub toc: bool,
+    /// 目录包含的标题级别
+    pub toc_depth: u8,
+    /// 目录的标题，为空时使用 pandoc 默认标题
+    pub toc_title: String,
+    /// 为标题编号
+    pub number_sections: bool,
+    /// 代码高亮样式，为空时使用 pandoc 默认样式
+    pub highlight_style: String,
+    /// 参考文献文件（BibTeX、CSL JSON 或 CSL YAML），与文档元数据中的 `bibliography` 一起使用
+    pub bibliography: Option<PathBuf>,
+    /// 引用格式的 CSL 样式文件，为空时使用 pandoc 默认的 Chicago 格式
+    pub csl_style: Option<PathBuf>,
+    /// 通过 `-M` 传给 pandoc 的元数据
+    pub metadata: Vec<(String, String)>,
+    /// 按顺序应用的过滤器
+    pub filters: Vec<FilterEntry>,
+    /// 追加到命令行末尾的其他 pandoc 参数
+    pub extra_args: String,
+}
+
+impl Default for ExportProfile {
+    fn default() -> Self {
+        Self {
+            name: "默认".to_string(),
+            format: ExportFormat::Docx,
+            reference_doc: None,
+            html_css: None,
+            html_embed_resources: true,
+            pdf_engine: "xelatex".to_string(),
+            pdf_cjk_font: String::new(),
+            toc: false,
+            toc_depth: 3,
+            toc_title: "目录".to_string(),
+            number_sections: false,
+            highlight_style: String::new(),
+            bibliography: None,
+            csl_style: None,
+            metadata: Vec::new(),
+            filters: Vec::new(),
+            extra_args: String::new(),
+        }
+    }
+}
+
+impl ExportProfile {
+    /// 当前选择的 PDF 引擎是否基于 LaTeX
+    pub fn pdf_engine_is_latex(&self) -> bool {
+        let engine = Path::new(&self.pdf_engine)
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        engine.ends_with("latex") || engine == "tectonic"
+    }
+
+    /// 将文档中的 `[TOC]` 标记替换为目录。DOCX 插入 Word 目录域，LaTeX 引擎的 PDF 插入
+    /// `\tableofcontents`；其他格式无法指定目录位置，删除标记并改为在开头生成目录。
+    /// 返回处理后的文本，以及是否还需要由 pandoc 的 `--toc` 生成目录。
+    pub fn place_toc(&self, markdown: &str) -> (String, bool) {
+        let mut output = String::with_capacity(markdown.len());
+        let mut marker_found = false;
+        let mut in_code_block = false;
+        for line in markdown.split_inclusive('\n') {
+            let trimmed = line.trim();
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                in_code_block = !in_code_block;
+            }
+            if in_code_block || !trimmed.eq_ignore_ascii_case(TOC_MARKER) {
+                output.push_str(line);
+                continue;
+            }
+            // 只有第一个标记生效，其余的标记直接删除
+            if !marker_found {
+                marker_found = true;
+                if let Some(block) = self.toc_block() {
+                    output.push_str(&block);
+                }
+            }
+        }
+
+        if !marker_found {
+            return (markdown.to_string(), self.toc);
+        }
+        let placed_inline = self.toc_block().is_some();
+        (output, !placed_inline)
+    }
+
+    /// 可以放在标记位置的目录代码块，当前格式不支持时返回 None
+    fn toc_block(&self) -> Option<String> {
+        match self.format {
+            ExportFormat::Docx => {
+                let title = if self.toc_title.trim().is_empty() {
+                    String::new()
+                } else {
+                    format!(
+                        "<w:p><w:pPr><w:pStyle w:val=\"TOCHeading\"/></w:pPr><w:r><w:t xml:space=\"preserve\">{}</w:t></w:r></w:p>",
+                        escape_xml(self.toc_title.trim())
+                    )
+                };
+                // 与 pandoc 的 `--toc` 生成的域相同；标记为 dirty，Word 打开文档时会提示更新目录
+                Some(format!(
+                    "\n```{{=openxml}}\n<w:sdt><w:sdtPr><w:docPartObj><w:docPartGallery w:val=\"Table of Contents\"/><w:docPartUnique/></w:docPartObj></w:sdtPr><w:sdtContent>{}<w:p><w:r><w:fldChar w:fldCharType=\"begin\" w:dirty=\"true\"/><w:instrText xml:space=\"preserve\">TOC \\o \"1-{}\" \\h \\z \\u</w:instrText><w:fldChar w:fldCharType=\"separate\"/><w:fldChar w:fldCharType=\"end\"/></w:r></w:p></w:sdtContent></w:sdt>\n```\n\n",
+                    title,
+                    self.toc_depth
+                ))
+            }
+            ExportFormat::Pdf if self.pdf_engine_is_latex() => {
+                let title = if self.toc_title.trim().is_empty() {
+                    String::new()
+                } else {
+                    format!("\\renewcommand{{\\contentsname}}{{{}}}\n", escape_latex(self.toc_title.trim()))
+                };
+                Some(format!(
+                    "\n```{{=latex}}\n{}\\setcounter{{tocdepth}}{{{}}}\n\\tableofcontents\n```\n\n",
+                    title,
+                    self.toc_depth
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// 导出的内容是否需要处理引用：方案指定了参考文献，或文档元数据中声明了参考文献
+    pub fn uses_citations(&self, markdown: &str) -> bool {
+        self.bibliography.is_some()
+            || metadata::front_matter_fields(markdown).is_some_and(|fields| {
+                fields.contains_key("bibliography") || fields.contains_key("references")
+            })
+    }
+
+    /// 添加与格式无关的通用参数：目录、编号、高亮、引用、元数据、过滤器和其他参数。
+    /// `toc` 表示是否由 pandoc 在文档开头生成目录，见 `place_toc()`。
+    pub fn apply_common_args(&self, command: &mut Command, toc: bool, citeproc: Citeproc) -> Result<(), String> {
+        if toc {
+            command.arg("--toc").arg(format!("--toc-depth={}", self.toc_depth));
+            if !self.toc_title.trim().is_empty() {
+                command.arg("-M").arg(format!("toc-title={}", self.toc_title.trim()));
+            }
+        }
+        if self.number_sections {
+            command.arg("--number-sections");
+        }
+        if !self.highlight_style.trim().is_empty() {
+            command.arg("--highlight-style").arg(self.highlight_style.trim());
+        }
+        // 引用处理要在用户的过滤器之前完成，过滤器才能看到生成的参考文献列表
+        if citeproc != Citeproc::None {
+            if let Some(bibliography) = &self.bibliography {
+                command.arg("--bibliography").arg(bibliography);
+            }
+            if let Some(csl) = &self.csl_style {
+                command.arg("--csl").arg(csl);
+            }
+            match citeproc {
+                Citeproc::Builtin => {
+                    command.arg("--citeproc");
+                }
+                Citeproc::Filter => {
+                    command.arg("--filter").arg("pandoc-citeproc");
+                }
+                Citeproc::None => {}
+            }
+        }
+        for (key, value) in &self.metadata {
+            if !key.trim().is_empty() {
+                command.arg("-M").arg(format!("{}={}", key.trim(), value));
+            }
+        }
+        for filter in self.filters.iter().filter(|filter| filter.enabled) {
+            if !filter.path.is_file() {
+                return Err(format!("找不到过滤器 {}，请在导出方案中移除或停用它。", filter.path.display()));
+            }
+            let option = if is_lua_filter(&filter.path) { "--lua-filter" } else { "--filter" };
+            command.arg(option).arg(&filter.path);
+        }
+        command.args(split_arguments(&self.extra_args)?);
+        Ok(())
+    }
+}
+
+/// 导出设置，跨重启保留
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportSettings {
+    pub profiles: Vec<ExportProfile>,
+    /// 导出时使用的方案在 `profiles` 中的位置
+    pub active_profile: usize,
+    /// 在过滤器管理中登记的过滤器，可以加入任何导出方案
+    pub filter_library: Vec<PathBuf>,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        Self {
+            profiles: vec![ExportProfile::default()],
+            active_profile: 0,
+            filter_library: Vec::new(),
+        }
+    }
+}
+
+impl ExportSettings {
+    pub fn active(&self) -> &ExportProfile {
+        &self.profiles[self.active_profile]
+    }
+
+    pub fn active_mut(&mut self) -> &mut ExportProfile {
+        &mut self.profiles[self.active_profile]
+    }
+
+    /// 确保至少有一个方案且当前方案有效，用于加载旧版或被手动修改过的设置
+    pub fn normalize(&mut self) {
+        if self.profiles.is_empty() {
+            self.profiles.push(ExportProfile::default());
+        }
+        self.active_profile = self.active_profile.min(self.profiles.len() - 1);
+        // 方案中用到的过滤器都应出现在过滤器管理中
+        let used: Vec<PathBuf> = self.profiles.iter()
+            .flat_map(|profile| profile.filters.iter().map(|filter| filter.path.clone()))
+            .collect();
+        for path in used {
+            self.register_filter(path);
+        }
+    }
+
+    /// 登记过滤器，已登记过的不重复添加
+    pub fn register_filter(&mut self, path: PathBuf) {
+        if !self.filter_library.contains(&path) {
+            self.filter_library.push(path);
+        }
+    }
+
+    /// 取消登记过滤器，并从所有导出方案中移除
+    pub fn unregister_filter(&mut self, path: &Path) {
+        self.filter_library.retain(|p| p != path);
+        for profile in &mut self.profiles {
+            profile.filters.retain(|filter| filter.path != path);
+        }
+    }
+
+    /// 生成一个不与现有方案重名的名称
+    pub fn unique_name(&self, base: &str) -> String {
+        let mut name = base.to_string();
+        let mut counter = 2;
+        while self.profiles.iter().any(|p| p.name == name) {
+            name = format!("{} {}", base, counter);
+            counter += 1;
+        }
+        name
+    }
+}
+
+/// 转义 XML 文本中的特殊字符
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// 转义 LaTeX 文本中的特殊字符
+fn escape_latex(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '%' | '&' | '#' | '_' | '$' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// 按空白拆分用户输入的命令行参数，支持用双引号包含空格
+pub fn split_arguments(text: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_token = false;
+    let mut in_quotes = false;
+    for c in text.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                in_token = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if in_token {
+                    args.push(std::mem::take(&mut current));
+                    in_token = false;
+                }
+            }
+            c => {
+                current.push(c);
+                in_token = true;
+            }
+        }
+    }
+    if in_quotes {
+        return Err("其他参数中的引号没有成对出现。".to_string());
+    }
+    if in_token {
+        args.push(current);
+    }
+    Ok(args)
+}
+
+/// 检查 PDF 引擎能否运行，找不到时返回说明
+pub fn check_pdf_engine(engine: &str) -> Result<(), String> {
+    if engine.trim().is_empty() {
+        return Err("尚未选择 PDF 引擎。".to_string());
+    }
+    let status = Command::new(engine)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    match status {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(format!(
+            "找不到 PDF 引擎“{}”。\n请安装该程序并确保它位于系统 PATH 中，或在“导出...”对话框中选择其他 PDF 引擎。",
+            engine
+        )),
+        Err(e) => Err(format!("无法运行 PDF 引擎“{}”：{}", engine, e)),
+    }
+}
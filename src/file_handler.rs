@@ -1,77 +1,542 @@
-use crate::state::MyApp;
+use crate::state::{MyApp, PendingAction};
+use crate::variables::VariableFormat;
+use crate::watcher;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+/// 合并对话框中的一个文件
+#[derive(Debug, Clone)]
+pub struct MergeEntry {
+    pub path: PathBuf,
+    /// 是否参与合并
+    pub include: bool,
+}
+
+/// 合并文件时插入在相邻文件之间的分隔方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeSeparator {
+    BlankLine,
+    PageBreak,
+    HorizontalRule,
+}
+
+impl MergeSeparator {
+    pub const ALL: [MergeSeparator; 3] = [
+        MergeSeparator::BlankLine,
+        MergeSeparator::PageBreak,
+        MergeSeparator::HorizontalRule,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            MergeSeparator::BlankLine => "空行",
+            MergeSeparator::PageBreak => "分页符",
+            MergeSeparator::HorizontalRule => "水平线",
+        }
+    }
+
+    pub fn as_markdown(&self) -> &'static str {
+        match self {
+            MergeSeparator::BlankLine => "\n\n",
+            // pandoc 导出 DOCX 时会将该原始 OpenXML 块转换为分页符
+            MergeSeparator::PageBreak => "\n\n```{=openxml}\n<w:p><w:r><w:br w:type=\"page\"/></w:r></w:p>\n```\n\n",
+            MergeSeparator::HorizontalRule => "\n\n---\n\n",
+        }
+    }
+}
+
+/// 合并时插入在每个文件内容之前的来源标记。它是一个 HTML 注释，
+/// 预览时会被隐藏，pandoc 导出 DOCX 时也会忽略它。
+/// 标记后面空一行，文件开头的 YAML front matter 才能继续被 pandoc 识别。
+const SOURCE_MARKER_PREFIX: &str = "<!-- rustf-source: ";
+const SOURCE_MARKER_SUFFIX: &str = " -->";
+
+pub fn source_marker(path: &Path) -> String {
+    format!("{}{}{}", SOURCE_MARKER_PREFIX, path.display(), SOURCE_MARKER_SUFFIX)
+}
+
+/// 来源文件内容的哈希，用于判断合并之后文件是否被修改过
+pub fn content_hash(text: &str) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn parse_source_marker(line: &str) -> Option<PathBuf> {
+    line.trim()
+        .strip_prefix(SOURCE_MARKER_PREFIX)?
+        .strip_suffix(SOURCE_MARKER_SUFFIX)
+        .map(PathBuf::from)
+}
+
+/// 判断一段 HTML 是否为来源标记（供预览时隐藏）
+pub fn is_source_marker(html: &str) -> bool {
+    parse_source_marker(html).is_some()
+}
+
+/// 按来源标记将合并后的文本拆分为 (原文件路径, 内容) 列表。
+/// 合并时插入的分隔和标记后的空行会被去掉，第一个标记之前的内容会被忽略。
+pub fn split_by_source_markers(text: &str) -> Vec<(PathBuf, String)> {
+    let mut segments: Vec<(PathBuf, String)> = Vec::new();
+    let mut after_marker = false;
+
+    for line in text.split_inclusive('\n') {
+        if let Some(path) = parse_source_marker(line) {
+            segments.push((path, String::new()));
+            after_marker = true;
+        } else if let Some((_, content)) = segments.last_mut() {
+            if !(after_marker && line == "\n") {
+                content.push_str(line);
+            }
+            after_marker = false;
+        }
+    }
+
+    // 除最后一段外，每段末尾都带有合并时插入的分隔。
+    // 空行是其他分隔的后缀，因此放在最后尝试。
+    let segment_count = segments.len();
+    for (_, content) in segments.iter_mut().take(segment_count.saturating_sub(1)) {
+        for separator in [MergeSeparator::PageBreak, MergeSeparator::HorizontalRule, MergeSeparator::BlankLine] {
+            if let Some(stripped) = content.strip_suffix(separator.as_markdown()) {
+                content.truncate(stripped.len());
+                break;
+            }
+        }
+    }
+
+    segments
+}
+
+/// 拆分回原文件时，每个文件的处理计划
+#[derive(Debug, Clone)]
+pub struct SplitSegment {
+    pub path: PathBuf,
+    pub content: String,
+    pub status: SplitStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitStatus {
+    Unchanged,
+    Modified,
+    /// 原文件已不存在，将重新创建
+    Missing,
+    /// 原文件在合并之后被其他程序修改过，为避免丢失这些修改，不会写回
+    ChangedOnDisk,
+}
+
+impl SplitStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SplitStatus::Unchanged => "未修改",
+            SplitStatus::Modified => "已修改",
+            SplitStatus::Missing => "原文件不存在，将新建",
+            SplitStatus::ChangedOnDisk => "合并后原文件已被修改，不会覆盖",
+        }
+    }
+
+    /// 确认后是否会写回该文件
+    pub fn will_write(&self) -> bool {
+        matches!(self, SplitStatus::Modified | SplitStatus::Missing)
+    }
+}
+
+/// 自然排序比较：数字部分按数值大小比较，其余部分按字符比较
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let mut x_digits = String::new();
+                while let Some(c) = a_chars.peek().copied().filter(|c| c.is_ascii_digit()) {
+                    x_digits.push(c);
+                    a_chars.next();
+                }
+                let mut y_digits = String::new();
+                while let Some(c) = b_chars.peek().copied().filter(|c| c.is_ascii_digit()) {
+                    y_digits.push(c);
+                    b_chars.next();
+                }
+
+                // 去掉前导零后先比较位数，再逐位比较，避免大数溢出
+                let x_trimmed = x_digits.trim_start_matches('0');
+                let y_trimmed = y_digits.trim_start_matches('0');
+                let ordering = x_trimmed.len()
+                    .cmp(&y_trimmed.len())
+                    .then_with(|| x_trimmed.cmp(y_trimmed))
+                    .then_with(|| x_digits.len().cmp(&y_digits.len()));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
 
 impl MyApp {
+    /// 执行一个会丢弃文档内容的操作；如果涉及的文档有未保存的修改，先弹出提示
+    pub fn request_action(&mut self, ctx: &egui::Context, action: PendingAction) {
+        if self.dirty_documents_for(&action).is_empty() {
+            self.perform_action(ctx, action);
+        } else {
+            self.pending_action = Some(action);
+            self.unsaved_dialog_open = true;
+        }
+    }
+
+    /// 不再检查未保存的修改，直接执行操作
+    pub fn perform_action(&mut self, ctx: &egui::Context, action: PendingAction) {
+        match action {
+            PendingAction::CloseDocument(id) => self.close_document(id),
+            PendingAction::Close => {
+                self.allow_close = true;
+                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            }
+        }
+    }
+
+    /// 操作涉及的、有未保存修改的文档索引
+    pub fn dirty_documents_for(&self, action: &PendingAction) -> Vec<usize> {
+        self.documents
+            .iter()
+            .enumerate()
+            .filter(|(_, doc)| doc.is_dirty)
+            .filter(|(_, doc)| match action {
+                PendingAction::CloseDocument(id) => doc.id == *id,
+                PendingAction::Close => true,
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+
     pub fn load_file(&mut self) {
-        let handle = rfd::FileDialog::new()
+        let handle = self.file_dialog()
             .add_filter("Markdown", &["md", "markdown"])
             .add_filter("Text", &["txt"])
             .pick_file();
-            
-        if let Some(path) = handle 
-            && let Ok(content) = std::fs::read_to_string(path) {
-            self.markdown_text = content;
+
+        if let Some(path) = handle {
+            self.open_file_from_path(path);
+        }
+    }
+
+    /// 从指定路径读取文件并在标签页中打开；如果该文件已经打开，则切换到对应的标签页
+    pub fn open_file_from_path(&mut self, path: PathBuf) {
+        if let Some(index) = self.documents.iter().position(|d| d.file_path.as_ref() == Some(&path)) {
+            self.active_document = index;
+            return;
         }
+
+        match std::fs::read_to_string(&path) {
+            Ok(content) => {
+                self.remember_directory(&path);
+                self.remember_recent_file(&path);
+                self.add_document(content, Some(path));
+            }
+            Err(e) => {
+                self.prune_recent_lists();
+                self.open_info_dialog("打开失败", &format!("无法读取文件 {}：{}", path.display(), e));
+            }
+        }
+    }
+
+    /// 保存当前文档；如果文档尚未保存过，则转为“另存为”。
+    /// 返回值表示内容是否已写入磁盘。
+    pub fn save_file(&mut self) -> bool {
+        self.save_document(self.active_document)
     }
-    
-    pub fn save_file(&self) {
-        let handle = rfd::FileDialog::new()
+
+    /// 选择新的路径保存当前文档，用户取消时返回 false
+    pub fn save_file_as(&mut self) -> bool {
+        self.save_document_as(self.active_document)
+    }
+
+    pub fn save_document(&mut self, index: usize) -> bool {
+        match self.documents[index].file_path.clone() {
+            // 文件在外部被修改过时不直接覆盖，切换到该文档并提示用户处理冲突
+            Some(_) if self.detect_external_change(index) => {
+                self.active_document = index;
+                false
+            }
+            Some(path) => self.write_document_to_path(index, &path),
+            None => self.save_document_as(index),
+        }
+    }
+
+    pub fn save_document_as(&mut self, index: usize) -> bool {
+        let mut dialog = self.file_dialog()
             .add_filter("Markdown", &["md", "markdown"])
-            .add_filter("Text", &["txt"])
-            .save_file();
-            
-        if let Some(path) = handle {
-            let _ = std::fs::write(path, &self.markdown_text);
+            .add_filter("Text", &["txt"]);
+
+        if let Some(path) = &self.documents[index].file_path {
+            if let Some(dir) = path.parent() {
+                dialog = dialog.set_directory(dir);
+            }
+            if let Some(name) = path.file_name() {
+                dialog = dialog.set_file_name(name.to_string_lossy());
+            }
+        } else if let Some(dir) = &self.documents[index].resource_dir {
+            // 保存在导入时图片所基于的目录中，文档中的相对路径才能继续使用
+            dialog = dialog.set_directory(dir);
+        }
+
+        match dialog.save_file() {
+            Some(path) => self.write_document_to_path(index, &path),
+            None => false,
+        }
+    }
+
+    fn write_document_to_path(&mut self, index: usize, path: &Path) -> bool {
+        match std::fs::write(path, &self.documents[index].markdown_text) {
+            Ok(_) => {
+                self.remember_directory(path);
+                let doc = &mut self.documents[index];
+                doc.file_path = Some(path.to_path_buf());
+                doc.disk_modified = watcher::modified_time(path);
+                doc.is_dirty = false;
+                doc.changed_on_disk = false;
+                true
+            }
+            Err(e) => {
+                self.open_info_dialog("保存失败", &format!("无法写入文件 {}：{}", path.display(), e));
+                false
+            }
         }
     }
-    
+
+    /// 根据当前文档路径和修改状态生成窗口标题
+    pub fn compose_window_title(&self) -> String {
+        let doc = self.doc();
+        let marker = if doc.is_dirty { "*" } else { "" };
+        format!("{}{} - {}", doc.display_name(), marker, crate::APP_NAME)
+    }
+
+    /// 选择要合并的文件，并打开合并对话框调整顺序和选项
     pub fn merge_files(&mut self) {
-        let files = rfd::FileDialog::new()
+        let files = self.file_dialog()
             .add_filter("Markdown", &["md", "markdown"])
             .add_filter("Text", &["txt"])
             .pick_files();
 
         if let Some(paths) = files {
-            if paths.len() <= 1 {
+            if paths.is_empty() {
                 return;
             }
+            self.remember_directory(&paths[0]);
+
+            self.merge_entries = paths
+                .into_iter()
+                .map(|path| MergeEntry { path, include: true })
+                .collect();
+            // 不依赖系统对话框返回的顺序
+            self.sort_merge_entries_by_name();
+            self.merge_dialog_open = true;
+        }
+    }
+
+    /// 按文件名自然排序，使 “2-” 排在 “10-” 之前
+    pub fn sort_merge_entries_by_name(&mut self) {
+        self.merge_entries.sort_by(|a, b| {
+            let a_name = a.path.file_name().map(|s| s.to_string_lossy()).unwrap_or_default();
+            let b_name = b.path.file_name().map(|s| s.to_string_lossy()).unwrap_or_default();
+            natural_cmp(&a_name, &b_name)
+        });
+    }
+
+    /// 按合并对话框中的顺序和选项合并文件，结果在新标签页中打开
+    pub fn perform_merge(&mut self) {
+        let entries: Vec<MergeEntry> = self.merge_entries
+            .iter()
+            .filter(|entry| entry.include)
+            .cloned()
+            .collect();
+        if entries.is_empty() {
+            self.open_info_dialog("合并文件", "没有选择任何要合并的文件。");
+            return;
+        }
+
+        let mut chapters = Vec::new();
+        let mut failures = Vec::new();
+        let mut merged_sources = HashMap::new();
+
+        for entry in &entries {
+            match std::fs::read(&entry.path) {
+                Ok(bytes) => match String::from_utf8(bytes) {
+                    Ok(content) => {
+                        self.remember_recent_file(&entry.path);
+                        if self.merge_insert_source_markers {
+                            merged_sources.insert(entry.path.clone(), content_hash(&content));
+                            chapters.push(format!("{}\n\n{}", source_marker(&entry.path), content));
+                        } else {
+                            chapters.push(content);
+                        }
+                    }
+                    Err(_) => failures.push(format!("{}：不是 UTF-8 编码的文本文件", entry.path.display())),
+                },
+                Err(e) => failures.push(format!("{}：{}", entry.path.display(), e)),
+            }
+        }
 
-            let mut combined_content = String::new();
+        if !chapters.is_empty() {
+            let combined_content = chapters.join(self.merge_separator.as_markdown());
+            // 合并结果是一个尚未保存的新文档
+            let document = self.add_document(combined_content, None);
+            document.is_dirty = true;
+            document.merged_sources = merged_sources;
+        }
 
-            for (index, path) in paths.iter().enumerate() {
-                if let Ok(content) = std::fs::read_to_string(path) {
-                    combined_content.push_str(&content);
+        if !failures.is_empty() {
+            self.open_info_dialog(
+                "部分文件未能合并",
+                &format!(
+                    "成功合并 {} 个文件，以下 {} 个文件读取失败：\n\n{}",
+                    chapters.len(),
+                    failures.len(),
+                    failures.join("\n")
+                ),
+            );
+        }
+    }
 
-                    if index < paths.len() - 1 {
-                        combined_content.push_str("\n\n");
+    /// 解析当前文档中的来源标记，准备将各段内容写回原文件，并打开确认对话框
+    pub fn prepare_split_to_sources(&mut self) {
+        let segments = split_by_source_markers(&self.doc().markdown_text);
+        if segments.is_empty() {
+            self.open_info_dialog(
+                "拆分回原文件",
+                "当前文档中没有来源标记。\n只有通过“合并文件”并勾选“插入来源标记”得到的文档才能拆分。",
+            );
+            return;
+        }
+
+        let merged_sources = &self.doc().merged_sources;
+        let split_plan = segments
+            .into_iter()
+            .map(|(path, content)| {
+                let status = match std::fs::read_to_string(&path) {
+                    Ok(existing) if existing == content => SplitStatus::Unchanged,
+                    Ok(existing) if merged_sources.get(&path).is_some_and(|&hash| hash != content_hash(&existing)) => {
+                        SplitStatus::ChangedOnDisk
+                    }
+                    Ok(_) => SplitStatus::Modified,
+                    Err(_) => SplitStatus::Missing,
+                };
+                SplitSegment { path, content, status }
+            })
+            .collect();
+        self.split_plan = split_plan;
+        self.split_dialog_open = true;
+    }
+
+    /// 将确认对话框中有变化的段落写回原文件
+    pub fn perform_split_to_sources(&mut self) {
+        let plan = std::mem::take(&mut self.split_plan);
+        let mut written = 0;
+        let mut failures = Vec::new();
+        let mut skipped_open = Vec::new();
+
+        for segment in plan.iter().filter(|s| s.status.will_write()) {
+            match std::fs::write(&segment.path, &segment.content) {
+                Ok(_) => {
+                    written += 1;
+                    // 写回的内容成为之后再次拆分时比较的基准
+                    if let Some(hash) = self.doc_mut().merged_sources.get_mut(&segment.path) {
+                        *hash = content_hash(&segment.content);
+                    }
+                    // 同步已在其他标签页中打开的原文件；有未保存修改的标签页保持不变
+                    if let Some(doc) = self.documents.iter_mut().find(|d| d.file_path.as_ref() == Some(&segment.path)) {
+                        if doc.is_dirty {
+                            skipped_open.push(segment.path.display().to_string());
+                        } else {
+                            doc.markdown_text = segment.content.clone();
+                            doc.disk_modified = watcher::modified_time(&segment.path);
+                        }
                     }
                 }
+                Err(e) => failures.push(format!("{}：{}", segment.path.display(), e)),
             }
+        }
 
-            if !combined_content.is_empty() {
-                self.markdown_text = combined_content;
-            }
+        let mut message = format!("已写回 {} 个文件。", written);
+        let changed_on_disk: Vec<String> = plan.iter()
+            .filter(|s| s.status == SplitStatus::ChangedOnDisk)
+            .map(|s| s.path.display().to_string())
+            .collect();
+        if !changed_on_disk.is_empty() {
+            message.push_str(&format!(
+                "\n\n以下文件在合并之后被其他程序修改过，未写回：\n{}",
+                changed_on_disk.join("\n")
+            ));
         }
+        if !skipped_open.is_empty() {
+            message.push_str(&format!(
+                "\n\n以下文件在其他标签页中有未保存的修改，标签页内容未更新：\n{}",
+                skipped_open.join("\n")
+            ));
+        }
+        if !failures.is_empty() {
+            message.push_str(&format!("\n\n以下文件写入失败：\n{}", failures.join("\n")));
+        }
+        self.open_info_dialog("拆分回原文件", &message);
     }
 
     pub fn export_template_variables(&mut self) {
         self.scan_and_update_markers();
-        if self.marker_values.is_empty() {
+        if self.doc().marker_values.is_empty() {
             self.open_info_dialog("导出模板变量", "没有找到任何模板变量，无需导出。");
             return;
         }
 
-        let mut content = String::new();
-        for (key, value) in &self.marker_values {
-            content.push_str(&format!("{}={}\n", key, value));
-        }
+        // 按变量名排序写出，多次导出的结果可以直接比较
+        let doc = self.doc();
+        let variables: Vec<(String, String)> = doc.template_markers.iter()
+            .map(|marker| (marker.clone(), doc.marker_values.get(marker).cloned().unwrap_or_default()))
+            .collect();
 
-        let handle = rfd::FileDialog::new()
-            .add_filter("Text", &["txt"])
-            .set_file_name("template_variables.txt")
+        // 文件对话框不会告知选择了哪个过滤器，格式只能按扩展名判断，
+        // 因此默认的 JSON 放在第一位，没有可识别扩展名的文件名补上 `.json`
+        let mut dialog = self.file_dialog();
+        for format in [VariableFormat::Json, VariableFormat::Yaml, VariableFormat::Csv, VariableFormat::KeyValue] {
+            dialog = dialog.add_filter(format.label(), format.extensions());
+        }
+        let handle = dialog
+            .set_file_name("template_variables.json")
             .save_file();
 
         if let Some(path) = handle {
-            match std::fs::write(path, &content) {
+            self.remember_directory(&path);
+            let (path, format) = match VariableFormat::from_extension(&path) {
+                Some(format) => (path, format),
+                None => {
+                    let mut path = path.into_os_string();
+                    path.push(".json");
+                    (PathBuf::from(path), VariableFormat::Json)
+                }
+            };
+            if format == VariableFormat::KeyValue
+                && variables.iter().any(|(_, value)| value.contains('\n')) {
+                self.open_info_dialog(
+                    "导出失败",
+                    "有变量的值包含换行，“名称=值”格式无法保存。请改为导出 JSON、YAML 或 CSV 文件。",
+                );
+                return;
+            }
+            match std::fs::write(path, format.format(&variables)) {
                 Ok(_) => self.open_info_dialog("成功", "模板变量已成功导出。"),
                 Err(e) => self.open_info_dialog("错误", &format!("导出失败：{}", e)),
             }
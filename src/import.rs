@@ -0,0 +1,459 @@
+use crate::jobs::{JobKind, JobRequest};
+use crate::state::MyApp;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// 按扩展名识别的输入格式及对应的 pandoc 读取器
+const PANDOC_READERS: [(&str, &str); 20] = [
+    ("docx", "docx"),
+    ("odt", "odt"),
+    ("rtf", "rtf"),
+    ("html", "html"),
+    ("htm", "html"),
+    ("xhtml", "html"),
+    ("tex", "latex"),
+    ("latex", "latex"),
+    ("epub", "epub"),
+    ("rst", "rst"),
+    ("org", "org"),
+    ("textile", "textile"),
+    ("wiki", "mediawiki"),
+    ("dbk", "docbook"),
+    ("xml", "docbook"),
+    ("ipynb", "ipynb"),
+    ("csv", "csv"),
+    ("tsv", "tsv"),
+    ("fb2", "fb2"),
+    ("opml", "opml"),
+];
+
+/// 可能内嵌图片、导入时需要提取媒体文件的 pandoc 读取器
+const MEDIA_READERS: [&str; 3] = ["docx", "odt", "epub"];
+
+/// 不需要经过 pandoc 转换、直接读取内容的文本格式
+const PLAIN_TEXT_EXTENSIONS: [&str; 4] = ["txt", "text", "md", "markdown"];
+
+/// 导入的内容放到哪里
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportTarget {
+    /// 在新标签页中打开
+    NewDocument,
+    /// 插入到指定文档的光标位置
+    Cursor { document_id: u64, anchor: CursorAnchor },
+}
+
+/// 导入开始时光标的位置及其前后的一小段文本。导入期间文档可能被继续编辑，
+/// 完成时根据前后文找回插入点，而不是直接使用原来的字符索引。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CursorAnchor {
+    char_index: usize,
+    before: String,
+    after: String,
+}
+
+impl CursorAnchor {
+    /// 记录多少个字符的前后文
+    const CONTEXT_CHARS: usize = 40;
+
+    pub fn capture(text: &str, char_index: usize) -> Self {
+        let byte_index = char_to_byte(text, char_index);
+        let before_start = text[..byte_index]
+            .char_indices()
+            .rev()
+            .nth(Self::CONTEXT_CHARS - 1)
+            .map_or(0, |(i, _)| i);
+        let after_end = char_to_byte(&text[byte_index..], Self::CONTEXT_CHARS) + byte_index;
+        CursorAnchor {
+            char_index,
+            before: text[before_start..byte_index].to_string(),
+            after: text[byte_index..after_end].to_string(),
+        }
+    }
+
+    /// 在文档当前的内容中找回插入点，返回字节位置。
+    /// 原位置的前后文没有变化时直接使用原位置；否则找离原位置最近的、前后文相同的地方；
+    /// 都找不到时插入到原位置所在行的开头，避免插到一个词的中间。
+    pub fn locate(&self, text: &str) -> usize {
+        let original = char_to_byte(text, self.char_index);
+        if text[..original].ends_with(&self.before) && text[original..].starts_with(&self.after) {
+            return original;
+        }
+
+        let both = format!("{}{}", self.before, self.after);
+        let candidates = [
+            (both.as_str(), self.before.len()),
+            (self.before.as_str(), self.before.len()),
+            (self.after.as_str(), 0),
+        ];
+        for (pattern, offset) in candidates {
+            if pattern.is_empty() {
+                continue;
+            }
+            let nearest = text.match_indices(pattern)
+                .map(|(start, _)| start + offset)
+                .min_by_key(|position| position.abs_diff(original));
+            if let Some(position) = nearest {
+                return position;
+            }
+        }
+        text[..original].rfind('\n').map_or(0, |i| i + 1)
+    }
+}
+
+/// 字符索引对应的字节位置，超出文本长度时返回文本末尾
+fn char_to_byte(text: &str, char_index: usize) -> usize {
+    text.char_indices().nth(char_index).map_or(text.len(), |(i, _)| i)
+}
+
+/// 导入时输出的 Markdown 风格
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MarkdownFlavor {
+    /// Pandoc Markdown，支持自定义样式、网格表格等全部扩展
+    #[default]
+    Pandoc,
+    CommonmarkX,
+    Gfm,
+    Strict,
+}
+
+impl MarkdownFlavor {
+    pub const ALL: [MarkdownFlavor; 4] = [
+        MarkdownFlavor::Pandoc,
+        MarkdownFlavor::CommonmarkX,
+        MarkdownFlavor::Gfm,
+        MarkdownFlavor::Strict,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            MarkdownFlavor::Pandoc => "Pandoc Markdown",
+            MarkdownFlavor::CommonmarkX => "CommonMark（含扩展）",
+            MarkdownFlavor::Gfm => "GitHub 风格 (GFM)",
+            MarkdownFlavor::Strict => "原始 Markdown",
+        }
+    }
+
+    fn pandoc_writer(&self) -> &'static str {
+        match self {
+            MarkdownFlavor::Pandoc => "markdown",
+            MarkdownFlavor::CommonmarkX => "commonmark_x",
+            MarkdownFlavor::Gfm => "gfm",
+            MarkdownFlavor::Strict => "markdown_strict",
+        }
+    }
+
+    /// 能否表示 `custom-style` 自定义样式的 div 和 span
+    pub fn supports_custom_styles(&self) -> bool {
+        matches!(self, MarkdownFlavor::Pandoc | MarkdownFlavor::CommonmarkX)
+    }
+}
+
+/// 输出文本的换行方式，对应 pandoc 的 `--wrap`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum WrapMode {
+    /// 按固定宽度自动换行（pandoc 默认）
+    #[default]
+    Auto,
+    /// 每个段落保持为一行
+    None,
+    /// 保留源文档中的换行
+    Preserve,
+}
+
+impl WrapMode {
+    pub const ALL: [WrapMode; 3] = [WrapMode::Auto, WrapMode::None, WrapMode::Preserve];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            WrapMode::Auto => "自动换行",
+            WrapMode::None => "不换行（每段一行）",
+            WrapMode::Preserve => "保留原换行",
+        }
+    }
+
+    fn pandoc_value(&self) -> &'static str {
+        match self {
+            WrapMode::Auto => "auto",
+            WrapMode::None => "none",
+            WrapMode::Preserve => "preserve",
+        }
+    }
+}
+
+/// Word 修订的处理方式，对应 pandoc 的 `--track-changes`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TrackChanges {
+    #[default]
+    Accept,
+    Reject,
+    /// 保留所有修订，以带有作者和时间的 span 标出
+    All,
+}
+
+impl TrackChanges {
+    pub const ALL: [TrackChanges; 3] = [TrackChanges::Accept, TrackChanges::Reject, TrackChanges::All];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            TrackChanges::Accept => "接受所有修订",
+            TrackChanges::Reject => "拒绝所有修订",
+            TrackChanges::All => "保留修订标记",
+        }
+    }
+
+    fn pandoc_value(&self) -> &'static str {
+        match self {
+            TrackChanges::Accept => "accept",
+            TrackChanges::Reject => "reject",
+            TrackChanges::All => "all",
+        }
+    }
+}
+
+/// 表格的输出形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TableStyle {
+    /// 由 pandoc 根据表格内容选择
+    #[default]
+    Auto,
+    /// 管道表格，编辑方便，但单元格中不能有多个段落
+    Pipe,
+    /// 网格表格，可以表示复杂的单元格内容
+    Grid,
+}
+
+impl TableStyle {
+    pub const ALL: [TableStyle; 3] = [TableStyle::Auto, TableStyle::Pipe, TableStyle::Grid];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            TableStyle::Auto => "自动选择",
+            TableStyle::Pipe => "管道表格",
+            TableStyle::Grid => "网格表格",
+        }
+    }
+}
+
+/// 导入选项，跨重启保留
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImportOptions {
+    /// 将 Word 中的样式保留为 `custom-style` 的 div 和 span（仅 DOCX）
+    pub preserve_styles: bool,
+    pub flavor: MarkdownFlavor,
+    pub wrap: WrapMode,
+    /// 仅 DOCX
+    pub track_changes: TrackChanges,
+    /// 仅 Pandoc Markdown
+    pub table_style: TableStyle,
+}
+
+impl ImportOptions {
+    /// 按选项为 pandoc 命令添加输入、输出格式等参数
+    pub fn apply_to(&self, command: &mut Command, reader: Option<&str>) {
+        if let Some(reader) = reader {
+            if reader == "docx" && self.preserve_styles && self.flavor.supports_custom_styles() {
+                command.arg("-f").arg("docx+styles");
+            } else {
+                command.arg("-f").arg(reader);
+            }
+        }
+
+        let mut writer = self.flavor.pandoc_writer().to_string();
+        if self.flavor == MarkdownFlavor::Pandoc {
+            match self.table_style {
+                TableStyle::Auto => {}
+                TableStyle::Pipe => writer.push_str("-grid_tables-simple_tables-multiline_tables"),
+                TableStyle::Grid => writer.push_str("-pipe_tables-simple_tables-multiline_tables"),
+            }
+        }
+        command.arg("-t").arg(writer);
+        command.arg(format!("--wrap={}", self.wrap.pandoc_value()));
+
+        if reader == Some("docx") {
+            command.arg(format!("--track-changes={}", self.track_changes.pandoc_value()));
+        }
+    }
+}
+
+/// 已选择文件、等待在导入选项对话框中确认的导入
+#[derive(Debug, Clone)]
+pub struct PendingImport {
+    pub input_path: PathBuf,
+    pub target: ImportTarget,
+    pub base_dir: Option<PathBuf>,
+}
+
+/// 导入任务完成后如何处理转换结果
+#[derive(Debug, Clone)]
+pub struct ImportPlan {
+    pub target: ImportTarget,
+    /// 导入内容中相对路径所基于的目录
+    pub base_dir: Option<PathBuf>,
+    /// pandoc 提取图片的目录，None 表示不提取
+    pub media_dir: Option<PathBuf>,
+}
+
+fn lowercase_extension(path: &Path) -> String {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// 根据扩展名选择 pandoc 读取器；无法识别时返回 None，由 pandoc 自行判断
+pub fn reader_for_path(path: &Path) -> Option<&'static str> {
+    let extension = lowercase_extension(path);
+    PANDOC_READERS
+        .iter()
+        .find(|(ext, _)| *ext == extension)
+        .map(|(_, reader)| *reader)
+}
+
+pub fn is_plain_text(path: &Path) -> bool {
+    PLAIN_TEXT_EXTENSIONS.contains(&lowercase_extension(path).as_str())
+}
+
+/// 将 pandoc 写入的指向媒体目录的绝对路径改写为相对于 `base_dir` 的路径，并统一使用 `/` 分隔。
+/// 媒体目录不在 `base_dir` 之下时只统一分隔符。
+pub fn rewrite_media_links(markdown: &str, media_dir: &Path, base_dir: &Path) -> String {
+    let replacement = match media_dir.strip_prefix(base_dir) {
+        Ok(relative) if relative.as_os_str().is_empty() => String::new(),
+        Ok(relative) => format!("{}/", relative.to_string_lossy().replace('\\', "/")),
+        Err(_) => format!("{}/", media_dir.to_string_lossy().replace('\\', "/")),
+    };
+    let pattern = format!(r#"{}[\\/]([^\s)>"']+)"#, regex::escape(&media_dir.to_string_lossy()));
+    let media_link = Regex::new(&pattern).unwrap();
+    media_link
+        .replace_all(markdown, |caps: &regex::Captures| {
+            format!("{}{}", replacement, caps[1].replace('\\', "/"))
+        })
+        .into_owned()
+}
+
+impl MyApp {
+    /// 选择一个文件导入。`into_cursor` 为 true 时插入到当前文档的光标处，否则在新标签页中打开。
+    pub fn import_file(&mut self, ctx: &egui::Context, into_cursor: bool) {
+        let supported: Vec<&str> = PANDOC_READERS
+            .iter()
+            .map(|(ext, _)| *ext)
+            .chain(PLAIN_TEXT_EXTENSIONS)
+            .collect();
+        let input_path = match self.file_dialog()
+            .add_filter("所有支持的格式", &supported)
+            .add_filter("Word 文档", &["docx"])
+            .add_filter("OpenDocument 文本", &["odt"])
+            .add_filter("网页", &["html", "htm"])
+            .add_filter("RTF", &["rtf"])
+            .add_filter("LaTeX", &["tex", "latex"])
+            .add_filter("纯文本", &PLAIN_TEXT_EXTENSIONS)
+            .add_filter("所有文件", &["*"])
+            .pick_file() {
+            Some(path) => path,
+            None => return,
+        };
+        self.remember_directory(&input_path);
+
+        // 插入位置在加入任务时确定，任务完成时即使切换了标签页也插入到原来的文档
+        let target = if into_cursor {
+            let doc = self.doc();
+            let char_index = egui::TextEdit::load_state(ctx, doc.editor_id())
+                .and_then(|state| state.cursor.char_range())
+                .map(|range| range.primary.index)
+                .unwrap_or_else(|| doc.markdown_text.chars().count());
+            let anchor = CursorAnchor::capture(&doc.markdown_text, char_index);
+            ImportTarget::Cursor { document_id: doc.id, anchor }
+        } else {
+            ImportTarget::NewDocument
+        };
+
+        // 插入到已保存的文档时，相对路径以该文档所在目录为准；否则以源文件所在目录为准
+        let base_dir = match &target {
+            ImportTarget::Cursor { .. } => self.doc().base_dir().map(Path::to_path_buf),
+            ImportTarget::NewDocument => None,
+        }
+        .or_else(|| input_path.parent().map(Path::to_path_buf));
+
+        if is_plain_text(&input_path) {
+            match std::fs::read_to_string(&input_path) {
+                Ok(content) => self.finish_import(content, ImportPlan { target, base_dir, media_dir: None }),
+                Err(e) => self.open_info_dialog("导入失败", &format!("无法读取文件 {}：{}", input_path.display(), e)),
+            }
+            return;
+        }
+
+        self.pending_import = Some(PendingImport { input_path, target, base_dir });
+        self.import_options_dialog_open = true;
+    }
+
+    /// 按导入选项对话框中的设置开始导入已选择的文件
+    pub fn start_pending_import(&mut self) {
+        let Some(PendingImport { input_path, target, base_dir }) = self.pending_import.take() else {
+            return;
+        };
+
+        let reader = reader_for_path(&input_path);
+        let media_dir = if reader.is_some_and(|reader| MEDIA_READERS.contains(&reader)) {
+            // 默认将图片放在文档旁边，由用户确认或另选文件夹；取消则放弃导入
+            let mut dialog = rfd::FileDialog::new().set_title("选择保存文档中图片的文件夹");
+            if let Some(dir) = &base_dir {
+                dialog = dialog.set_directory(dir);
+            }
+            match dialog.pick_folder() {
+                Some(dir) => Some(dir),
+                None => return,
+            }
+        } else {
+            None
+        };
+
+        let mut command = Command::new(&self.pandoc.executable);
+        command.arg(&input_path);
+        self.import_options.apply_to(&mut command, reader);
+        if let Some(dir) = &media_dir {
+            command.arg("--extract-media").arg(dir);
+        }
+
+        let file_name = input_path.file_name()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        self.jobs.enqueue(JobRequest {
+            description: format!("导入 {}", file_name),
+            kind: JobKind::Import(ImportPlan { target, base_dir, media_dir }),
+            command,
+            temp_input: None,
+            source_document: None,
+        });
+    }
+
+    /// 将导入的 Markdown 内容放到目标位置；目标文档已经关闭时改为在新标签页中打开
+    pub fn finish_import(&mut self, markdown_content: String, plan: ImportPlan) {
+        let markdown_content = match (&plan.media_dir, &plan.base_dir) {
+            (Some(media_dir), Some(base_dir)) => rewrite_media_links(&markdown_content, media_dir, base_dir),
+            _ => markdown_content,
+        };
+
+        let cursor_target = match plan.target {
+            ImportTarget::Cursor { document_id, anchor } => {
+                self.document_index(document_id).map(|index| (index, anchor))
+            }
+            ImportTarget::NewDocument => None,
+        };
+
+        let Some((index, anchor)) = cursor_target else {
+            // 导入的内容在新标签页中打开，尚未保存为 Markdown 文件
+            let doc = self.add_document(markdown_content, None);
+            doc.is_dirty = true;
+            doc.resource_dir = plan.base_dir;
+            return;
+        };
+        let doc = &mut self.documents[index];
+        let byte_index = anchor.locate(&doc.markdown_text);
+        let char_index = doc.markdown_text[..byte_index].chars().count();
+        doc.markdown_text.insert_str(byte_index, &markdown_content);
+        doc.is_dirty = true;
+        doc.pending_cursor = Some(char_index + markdown_content.chars().count());
+        self.active_document = index;
+    }
+}
@@ -0,0 +1,175 @@
+use std::path::{Path, PathBuf};
+
+/// 最大嵌套深度，防止异常情况下无限展开
+const MAX_INCLUDE_DEPTH: usize = 32;
+
+/// 展开包含指令时遇到的问题
+#[derive(Debug, Clone)]
+pub struct IncludeError {
+    /// 出现问题的指令所在文件，None 表示当前尚未保存的文档
+    pub file: Option<PathBuf>,
+    /// 指令所在的行号（从 1 开始）
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for IncludeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let file = self.file
+            .as_ref()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|| "当前文档".to_string());
+        write!(f, "{} 第 {} 行：{}", file, self.line, self.message)
+    }
+}
+
+/// 展开后的文本以及展开过程中遇到的问题
+#[derive(Debug, Clone, Default)]
+pub struct ExpandedText {
+    pub text: String,
+    pub errors: Vec<IncludeError>,
+}
+
+/// 如果该行是一条包含指令，返回被包含文件的路径。
+/// 支持独占一行的 `{{> path/to/file.md}}` 和 `!include path/to/file.md` 两种写法。
+pub fn parse_include_directive(line: &str) -> Option<&str> {
+    let trimmed = line.trim();
+    let path = if let Some(rest) = trimmed.strip_prefix("{{>") {
+        rest.strip_suffix("}}")?
+    } else {
+        trimmed.strip_prefix("!include ")?
+    };
+    let path = path.trim().trim_matches('"');
+    (!path.is_empty()).then_some(path)
+}
+
+/// 快速判断文本中是否可能包含指令，避免对普通文档做多余的展开
+pub fn has_include_directives(text: &str) -> bool {
+    (text.contains("{{>") || text.contains("!include ")) && text.lines().any(|line| parse_include_directive(line).is_some())
+}
+
+/// 递归展开文本中的包含指令，代码块中的指令保持原样。
+/// 相对路径以所在文件的目录为基准；`source_file` 为 None 时只能使用绝对路径。
+/// 无法展开的指令保持原样，并记录到返回的错误列表中。
+pub fn expand_includes(text: &str, source_file: Option<&Path>) -> ExpandedText {
+    let mut expanded = ExpandedText::default();
+    let mut stack = Vec::new();
+    if let Some(path) = source_file {
+        stack.push(canonical(path));
+    }
+    expand_into(text, source_file, &mut stack, &mut expanded);
+    expanded
+}
+
+fn expand_into(text: &str, source_file: Option<&Path>, stack: &mut Vec<PathBuf>, expanded: &mut ExpandedText) {
+    let mut in_code_block = false;
+    for (index, line) in text.split_inclusive('\n').enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code_block = !in_code_block;
+        }
+        let Some(include_path) = parse_include_directive(line).filter(|_| !in_code_block) else {
+            expanded.text.push_str(line);
+            continue;
+        };
+
+        let mut report = |message: String| {
+            expanded.errors.push(IncludeError {
+                file: source_file.map(Path::to_path_buf),
+                line: index + 1,
+                message,
+            });
+        };
+
+        let include_path = Path::new(include_path);
+        let resolved = if include_path.is_absolute() {
+            include_path.to_path_buf()
+        } else if let Some(dir) = source_file.and_then(Path::parent) {
+            dir.join(include_path)
+        } else {
+            report(format!("文档尚未保存，无法解析相对路径 {}", include_path.display()));
+            expanded.text.push_str(line);
+            continue;
+        };
+
+        let key = canonical(&resolved);
+        if stack.contains(&key) {
+            report(format!("循环包含 {}", resolved.display()));
+            expanded.text.push_str(line);
+            continue;
+        }
+        if stack.len() >= MAX_INCLUDE_DEPTH {
+            report(format!("包含层级超过 {} 层", MAX_INCLUDE_DEPTH));
+            expanded.text.push_str(line);
+            continue;
+        }
+
+        match std::fs::read_to_string(&resolved) {
+            Ok(content) => {
+                stack.push(key);
+                expand_into(&content, Some(&resolved), stack, expanded);
+                stack.pop();
+                // 保证被包含内容之后仍然换行，不与下一行粘连
+                if line.ends_with('\n') && !expanded.text.ends_with('\n') {
+                    expanded.text.push('\n');
+                }
+            }
+            Err(e) => {
+                report(format!("无法读取被包含的文件 {}：{}", resolved.display(), e));
+                expanded.text.push_str(line);
+            }
+        }
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_both_directive_forms() {
+        assert_eq!(parse_include_directive("{{> chapters/a.md}}"), Some("chapters/a.md"));
+        assert_eq!(parse_include_directive("  !include \"b.md\"\n"), Some("b.md"));
+        assert_eq!(parse_include_directive("文中的 {{> a.md}} 不是指令"), None);
+        assert_eq!(parse_include_directive("{{>}}"), None);
+    }
+
+    #[test]
+    fn expands_nested_files_but_not_code_blocks() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("a.md"), "A 开始\n{{> sub/b.md}}\nA 结束").unwrap();
+        std::fs::write(dir.path().join("sub/b.md"), "B").unwrap();
+        let main = dir.path().join("main.md");
+
+        let text = "{{> a.md}}\n```\n{{> a.md}}\n```\n";
+        let expanded = expand_includes(text, Some(&main));
+        assert!(expanded.errors.is_empty(), "{:?}", expanded.errors);
+        assert_eq!(expanded.text, "A 开始\nB\nA 结束\n```\n{{> a.md}}\n```\n");
+    }
+
+    #[test]
+    fn reports_cycles_and_missing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let main = dir.path().join("main.md");
+        std::fs::write(&main, "{{> main.md}}\n").unwrap();
+
+        let expanded = expand_includes("{{> main.md}}\n!include missing.md\n", Some(&main));
+        assert_eq!(expanded.text, "{{> main.md}}\n!include missing.md\n");
+        assert_eq!(expanded.errors.len(), 2);
+        assert_eq!(expanded.errors[0].line, 1);
+        assert_eq!(expanded.errors[1].line, 2);
+    }
+
+    #[test]
+    fn relative_paths_need_a_saved_document() {
+        let expanded = expand_includes("{{> a.md}}\n", None);
+        assert_eq!(expanded.text, "{{> a.md}}\n");
+        assert_eq!(expanded.errors.len(), 1);
+        assert!(expanded.errors[0].file.is_none());
+    }
+}
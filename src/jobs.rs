@@ -0,0 +1,313 @@
+use crate::import::ImportPlan;
+use std::collections::VecDeque;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tempfile::NamedTempFile;
+
+/// 有任务在执行时检查其状态的时间间隔
+pub const JOB_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// pandoc 退出后最多再等待多长时间读取剩余的输出。
+/// pandoc 启动的 PDF 引擎等子进程可能仍然占用管道，不能一直等到管道关闭。
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// 任务完成后如何处理 pandoc 的输出
+#[derive(Debug, Clone)]
+pub enum JobKind {
+    /// 导出到文件，pandoc 通过 `-o` 直接写入目标文件
+    Export { output_path: PathBuf },
+    /// 导入为 Markdown，转换结果从标准输出读取
+    Import(ImportPlan),
+}
+
+/// 等待执行的 pandoc 任务
+pub struct JobRequest {
+    /// 在状态栏和队列中显示的说明
+    pub description: String,
+    pub kind: JobKind,
+    /// 已设置好参数的 pandoc 命令
+    pub command: Command,
+    /// 任务执行期间需要保留的临时输入文件，任务结束后自动删除
+    pub temp_input: Option<NamedTempFile>,
+    /// 任务内容来自哪个文档，用于将 pandoc 的警告定位到编辑器中的行
+    pub source_document: Option<u64>,
+}
+
+/// 任务的执行结果
+pub enum JobOutcome {
+    Succeeded { stdout: Vec<u8> },
+    /// 失败原因记录在 `FinishedJob::stderr` 中
+    Failed,
+    Cancelled,
+}
+
+/// 已结束的任务
+pub struct FinishedJob {
+    pub description: String,
+    pub kind: JobKind,
+    pub outcome: JobOutcome,
+    pub source_document: Option<u64>,
+    /// 执行的完整命令行
+    pub command_line: String,
+    pub duration: Duration,
+    /// pandoc 的退出码，未能启动或被终止时为 None
+    pub exit_code: Option<i32>,
+    /// pandoc 输出的警告和错误信息
+    pub stderr: String,
+}
+
+struct QueuedJob {
+    id: u64,
+    request: JobRequest,
+}
+
+struct RunningJob {
+    description: String,
+    kind: JobKind,
+    source_document: Option<u64>,
+    command_line: String,
+    started_at: Instant,
+    child: Child,
+    stdout_reader: OutputReader,
+    stderr_reader: OutputReader,
+    /// pandoc 的退出状态和退出时间，之后还要等待输出读取完毕
+    exited: Option<(std::io::Result<ExitStatus>, Instant)>,
+    cancel_requested: bool,
+    _temp_input: Option<NamedTempFile>,
+}
+
+impl RunningJob {
+    fn start(request: JobRequest) -> Result<Self, Box<FinishedJob>> {
+        let JobRequest { description, kind, mut command, temp_input, source_document } = request;
+        let command_line = format_command_line(&command);
+        let spawned = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(e) => {
+                let message = format!(
+                    "无法执行 Pandoc 命令。\n请确保 Pandoc 已正确安装并位于系统 PATH 中，或与本程序在同一目录下，也可以在“工具”菜单中设置 Pandoc 的路径。\n\n错误详情: {}",
+                    e
+                );
+                return Err(Box::new(FinishedJob {
+                    description,
+                    kind,
+                    outcome: JobOutcome::Failed,
+                    source_document,
+                    command_line,
+                    duration: Duration::ZERO,
+                    exit_code: None,
+                    stderr: message,
+                }));
+            }
+        };
+
+        // 在独立线程中读取输出，避免管道写满后 pandoc 阻塞
+        let stdout_reader = OutputReader::spawn(child.stdout.take());
+        let stderr_reader = OutputReader::spawn(child.stderr.take());
+
+        Ok(Self {
+            description,
+            kind,
+            source_document,
+            command_line,
+            started_at: Instant::now(),
+            child,
+            stdout_reader,
+            stderr_reader,
+            exited: None,
+            cancel_requested: false,
+            _temp_input: temp_input,
+        })
+    }
+
+    /// 检查 pandoc 是否已退出，并且输出已经读完或不必再等待
+    fn is_done(&mut self) -> bool {
+        if self.exited.is_none() {
+            let status = match self.child.try_wait() {
+                Ok(None) => return false,
+                Ok(Some(status)) => Ok(status),
+                Err(e) => {
+                    let _ = self.child.kill();
+                    Err(e)
+                }
+            };
+            self.exited = Some((status, Instant::now()));
+        }
+        let exited_at = self.exited.as_ref().map_or_else(Instant::now, |(_, at)| *at);
+        (self.stdout_reader.is_finished() && self.stderr_reader.is_finished())
+            || self.cancel_requested
+            || exited_at.elapsed() > OUTPUT_DRAIN_TIMEOUT
+    }
+
+    /// 整理已结束任务的结果。仍在占用管道的读取线程不再等待，只使用已经读到的输出。
+    fn finish(mut self) -> FinishedJob {
+        let status = match self.exited.take() {
+            Some((status, _)) => status,
+            None => Err(std::io::Error::other("任务尚未结束")),
+        };
+        let stdout = self.stdout_reader.take();
+        let mut stderr = String::from_utf8_lossy(&self.stderr_reader.take()).into_owned();
+        let exit_code = status.as_ref().ok().and_then(|status| status.code());
+
+        let outcome = match status {
+            _ if self.cancel_requested => JobOutcome::Cancelled,
+            Ok(status) if status.success() => JobOutcome::Succeeded { stdout },
+            Ok(_) => JobOutcome::Failed,
+            Err(e) => {
+                stderr.push_str(&format!("无法获取 Pandoc 的运行状态: {}", e));
+                JobOutcome::Failed
+            }
+        };
+        FinishedJob {
+            description: self.description,
+            kind: self.kind,
+            outcome,
+            source_document: self.source_document,
+            command_line: self.command_line,
+            duration: self.started_at.elapsed(),
+            exit_code,
+            stderr,
+        }
+    }
+}
+
+/// 将命令格式化为可以复制到终端中执行的一行文本
+fn format_command_line(command: &Command) -> String {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|part| {
+            let part = part.to_string_lossy();
+            if part.contains(char::is_whitespace) {
+                format!("\"{}\"", part)
+            } else {
+                part.into_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// 在独立线程中读取子进程的一个输出管道。读到的内容随时可以取出，
+/// 因此界面线程不需要等待读取线程结束。
+struct OutputReader {
+    buffer: Arc<Mutex<Vec<u8>>>,
+    handle: JoinHandle<()>,
+}
+
+impl OutputReader {
+    fn spawn<R: Read + Send + 'static>(source: Option<R>) -> Self {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let shared = Arc::clone(&buffer);
+        let handle = std::thread::spawn(move || {
+            let Some(mut source) = source else {
+                return;
+            };
+            let mut chunk = [0u8; 8192];
+            loop {
+                match source.read(&mut chunk) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => shared.lock().unwrap_or_else(|e| e.into_inner()).extend_from_slice(&chunk[..n]),
+                }
+            }
+        });
+        Self { buffer, handle }
+    }
+
+    /// 管道是否已经关闭、全部输出都已读取
+    fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.buffer.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+/// 按顺序逐个执行 pandoc 任务，可以取消正在执行的任务或移除排队中的任务
+#[derive(Default)]
+pub struct JobManager {
+    queue: VecDeque<QueuedJob>,
+    running: Option<RunningJob>,
+    next_id: u64,
+}
+
+impl JobManager {
+    /// 将任务加入队列，返回任务编号
+    pub fn enqueue(&mut self, request: JobRequest) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.queue.push_back(QueuedJob { id, request });
+        id
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.running.is_some() || !self.queue.is_empty()
+    }
+
+    /// 正在执行的任务的说明和已用时间
+    pub fn running(&self) -> Option<(&str, Duration)> {
+        self.running
+            .as_ref()
+            .map(|job| (job.description.as_str(), job.started_at.elapsed()))
+    }
+
+    /// 排队中的任务编号和说明
+    pub fn queued(&self) -> impl Iterator<Item = (u64, &str)> {
+        self.queue.iter().map(|job| (job.id, job.request.description.as_str()))
+    }
+
+    /// 终止正在执行的 pandoc 进程，下一次 `poll()` 时报告为已取消
+    pub fn cancel_running(&mut self) {
+        if let Some(job) = &mut self.running
+            && !job.cancel_requested {
+            job.cancel_requested = true;
+            let _ = job.child.kill();
+        }
+    }
+
+    pub fn remove_queued(&mut self, id: u64) {
+        self.queue.retain(|job| job.id != id);
+    }
+
+    /// 清空队列并终止正在执行的任务，用于退出程序时
+    pub fn cancel_all(&mut self) {
+        self.queue.clear();
+        if let Some(mut job) = self.running.take() {
+            let _ = job.child.kill();
+            let _ = job.child.wait();
+        }
+    }
+
+    /// 检查正在执行的任务是否结束，并启动队列中的下一个任务。返回本次结束的任务。
+    pub fn poll(&mut self) -> Vec<FinishedJob> {
+        let mut finished = Vec::new();
+        loop {
+            if let Some(job) = &mut self.running {
+                if !job.is_done() {
+                    break;
+                }
+                if let Some(job) = self.running.take() {
+                    finished.push(job.finish());
+                }
+            }
+
+            let Some(next) = self.queue.pop_front() else {
+                break;
+            };
+            match RunningJob::start(next.request) {
+                Ok(job) => self.running = Some(job),
+                Err(failed) => finished.push(*failed),
+            }
+        }
+        finished
+    }
+}
@@ -2,21 +2,40 @@
 
 // 声明新模块
 mod app;
+mod batch;
+mod citations;
+mod document;
+mod export;
 mod font_utils;
+mod import;
+mod includes;
+mod jobs;
+mod metadata;
 mod state;
 mod ui;
 mod file_handler;
 mod pandoc;
+mod pandoc_locator;
+mod pandoc_log;
+mod preview;
+mod project;
+mod recovery;
+mod settings;
+mod variables;
+mod watcher;
 
 
 // 导入需要的项
 use state::MyApp;
 use eframe::{NativeOptions, App};
 
+/// 应用名称，同时用作窗口标题和 eframe 数据目录的标识
+pub const APP_NAME: &str = "文档风格转换器";
+
 fn main() {
     let native_options = NativeOptions::default();
     eframe::run_native(
-        "文档风格转换器",
+        APP_NAME,
         native_options,
         // 使用 app 模块中的构造函数来创建应用实例
         Box::new(|cc| Ok(Box::new(MyApp::new(cc)) as Box<dyn App>)),
@@ -0,0 +1,446 @@
+use crate::document::Document;
+use serde_yaml_ng::{Mapping, Value};
+
+/// 文档开头的 YAML 元数据块
+pub struct FrontMatter<'a> {
+    /// 两条分隔线之间的 YAML 文本
+    pub yaml: &'a str,
+    /// 元数据块（含结束分隔线）之后正文开始的字节位置
+    pub end: usize,
+}
+
+/// 查找文档开头以 `---` 开始、以 `---` 或 `...` 结束的元数据块
+pub fn find_front_matter(text: &str) -> Option<FrontMatter<'_>> {
+    let first_line_end = text.find('\n')?;
+    if text[..first_line_end].trim_end() != "---" {
+        return None;
+    }
+    let yaml_start = first_line_end + 1;
+    let mut pos = yaml_start;
+    for line in text[yaml_start..].split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if trimmed == "---" || trimmed == "..." {
+            return Some(FrontMatter {
+                yaml: &text[yaml_start..pos],
+                end: pos + line.len(),
+            });
+        }
+        pos += line.len();
+    }
+    None
+}
+
+/// YAML 元数据中的语法错误
+#[derive(Debug, Clone, PartialEq)]
+pub struct YamlError {
+    pub message: String,
+    /// 错误在文档中的行号（从 1 开始）
+    pub line: Option<usize>,
+}
+
+impl std::fmt::Display for YamlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "第 {} 行：{}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// 自定义字段的值
+#[derive(Debug, Clone, PartialEq)]
+pub enum CustomValue {
+    /// 文本、数字或布尔值，可以在面板中直接编辑
+    Text(String),
+    /// 列表或映射等复杂的值，只能在编辑区中修改
+    Structured(Value),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomField {
+    pub key: String,
+    pub value: CustomValue,
+}
+
+/// 元数据面板中编辑的字段。pandoc 用它们生成标题块，并写入 DOCX 的文档属性。
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DocumentMetadata {
+    pub title: String,
+    pub subtitle: String,
+    pub authors: Vec<String>,
+    pub date: String,
+    pub abstract_text: String,
+    /// 以逗号分隔的关键词，写入时转换为列表
+    pub keywords: String,
+    pub custom: Vec<CustomField>,
+}
+
+impl DocumentMetadata {
+    /// 解析元数据块中的 YAML 文本；`first_line` 是 YAML 第一行在文档中的行号，用于定位错误
+    pub fn parse(yaml: &str, first_line: usize) -> Result<Self, YamlError> {
+        let mut metadata = DocumentMetadata::default();
+        if yaml.trim().is_empty() {
+            return Ok(metadata);
+        }
+
+        let value: Value = serde_yaml_ng::from_str(yaml).map_err(|e| YamlError {
+            message: e.to_string(),
+            line: e.location().map(|location| first_line + location.line() - 1),
+        })?;
+        let mapping = match value {
+            Value::Mapping(mapping) => mapping,
+            Value::Null => return Ok(metadata),
+            _ => {
+                return Err(YamlError {
+                    message: "元数据必须由“名称: 值”形式的条目组成。".to_string(),
+                    line: Some(first_line),
+                });
+            }
+        };
+
+        for (key, value) in mapping {
+            let key = match key {
+                Value::String(key) => key,
+                other => scalar_text(&other).unwrap_or_default(),
+            };
+            let known = match key.as_str() {
+                "title" => scalar_text(&value).map(|text| metadata.title = text),
+                "subtitle" => scalar_text(&value).map(|text| metadata.subtitle = text),
+                "date" => scalar_text(&value).map(|text| metadata.date = text),
+                "abstract" => scalar_text(&value).map(|text| metadata.abstract_text = text),
+                "author" => text_list(&value).map(|authors| metadata.authors = authors),
+                "keywords" => text_list(&value).map(|keywords| metadata.keywords = keywords.join(", ")),
+                _ => None,
+            };
+            if known.is_none() {
+                let value = match scalar_text(&value) {
+                    Some(text) => CustomValue::Text(text),
+                    None => CustomValue::Structured(value),
+                };
+                metadata.custom.push(CustomField { key, value });
+            }
+        }
+        Ok(metadata)
+    }
+
+    /// 生成元数据块中的 YAML 文本；所有字段都为空时返回 None
+    pub fn to_yaml(&self) -> Option<String> {
+        let mapping = self.to_mapping();
+        if mapping.is_empty() {
+            None
+        } else {
+            serde_yaml_ng::to_string(&Value::Mapping(mapping)).ok()
+        }
+    }
+
+    /// 在原有的 YAML 文本上只改写与 `previous` 相比有变化的条目，
+    /// 未修改条目的注释、引号和多行文本的写法保持不变，新增的条目追加在末尾。
+    /// 原文本无法按条目拆分时重新生成整个元数据块。
+    pub fn update_yaml(&self, yaml: &str, previous: &DocumentMetadata) -> Option<String> {
+        let entries = self.to_mapping();
+        if entries.is_empty() {
+            return None;
+        }
+        let Some(segments) = split_entries(yaml) else {
+            return self.to_yaml();
+        };
+        let previous = previous.to_mapping();
+
+        let mut output = String::with_capacity(yaml.len());
+        let mut written = Vec::new();
+        for (key, text) in segments {
+            let Some(key) = key else {
+                output.push_str(&text);
+                continue;
+            };
+            let yaml_key = Value::String(key.clone());
+            let value = entries.get(&yaml_key);
+            if value == previous.get(&yaml_key) {
+                output.push_str(&text);
+            } else if let Some(value) = value {
+                output.push_str(&entry_yaml(&key, value));
+            }
+            written.push(yaml_key);
+        }
+
+        if !output.is_empty() && !output.ends_with('\n') {
+            output.push('\n');
+        }
+        for (key, value) in &entries {
+            if !written.contains(key) {
+                output.push_str(&entry_yaml(&scalar_text(key).unwrap_or_default(), value));
+            }
+        }
+        Some(output)
+    }
+
+    /// 按写出的顺序列出各个非空字段
+    fn to_mapping(&self) -> Mapping {
+        let mut mapping = Mapping::new();
+        insert_text(&mut mapping, "title", &self.title);
+        insert_text(&mut mapping, "subtitle", &self.subtitle);
+
+        // 多位作者写成列表，pandoc 才会在 DOCX 中分别列出
+        let authors: Vec<&str> = self.authors.iter()
+            .map(|author| author.trim())
+            .filter(|author| !author.is_empty())
+            .collect();
+        match authors.as_slice() {
+            [] => {}
+            [author] => {
+                mapping.insert("author".into(), Value::String(author.to_string()));
+            }
+            _ => {
+                let list = authors.iter().map(|author| Value::String(author.to_string())).collect();
+                mapping.insert("author".into(), Value::Sequence(list));
+            }
+        }
+
+        insert_text(&mut mapping, "date", &self.date);
+        insert_text(&mut mapping, "abstract", &self.abstract_text);
+
+        let keywords: Vec<Value> = self.keywords
+            .split([',', '，'])
+            .map(str::trim)
+            .filter(|keyword| !keyword.is_empty())
+            .map(|keyword| Value::String(keyword.to_string()))
+            .collect();
+        if !keywords.is_empty() {
+            mapping.insert("keywords".into(), Value::Sequence(keywords));
+        }
+
+        for field in &self.custom {
+            let key = field.key.trim();
+            if key.is_empty() {
+                continue;
+            }
+            let value = match &field.value {
+                CustomValue::Text(text) => text_value(text),
+                CustomValue::Structured(value) => value.clone(),
+            };
+            mapping.insert(key.into(), value);
+        }
+        mapping
+    }
+}
+
+/// 将元数据块的 YAML 文本拆分为顶层条目，返回 (条目名, 原文) 列表。
+/// 空行和顶格的注释单独成段，条目名为 None；无法确定某个条目的名称时返回 None。
+fn split_entries(yaml: &str) -> Option<Vec<(Option<String>, String)>> {
+    let mut segments: Vec<(Option<String>, String)> = Vec::new();
+    // 条目之后的空行和注释，只有后面还有缩进的行时才属于该条目（例如多行文本中的空行）
+    let mut pending = String::new();
+
+    for line in yaml.split_inclusive('\n') {
+        let in_entry = segments.last().is_some_and(|(key, _)| key.is_some());
+        if line.trim().is_empty() || line.starts_with('#') {
+            if in_entry {
+                pending.push_str(line);
+            } else {
+                segments.push((None, line.to_string()));
+            }
+        } else if line.starts_with([' ', '\t', '-']) && in_entry {
+            let (_, text) = segments.last_mut().expect("in_entry implies a segment");
+            text.push_str(&std::mem::take(&mut pending));
+            text.push_str(line);
+        } else {
+            if !pending.is_empty() {
+                segments.push((None, std::mem::take(&mut pending)));
+            }
+            segments.push((Some(String::new()), line.to_string()));
+        }
+    }
+    if !pending.is_empty() {
+        segments.push((None, pending));
+    }
+
+    for (key, text) in &mut segments {
+        if key.is_none() {
+            continue;
+        }
+        let mapping: Mapping = serde_yaml_ng::from_str(text).ok()?;
+        let [(entry_key, _)] = mapping.iter().collect::<Vec<_>>()[..] else {
+            return None;
+        };
+        *key = Some(scalar_text(entry_key)?);
+    }
+    Some(segments)
+}
+
+/// 单个条目的 YAML 文本
+fn entry_yaml(key: &str, value: &Value) -> String {
+    let mut mapping = Mapping::new();
+    mapping.insert(key.into(), value.clone());
+    serde_yaml_ng::to_string(&Value::Mapping(mapping)).unwrap_or_default()
+}
+
+/// 非空的文本去掉首尾空白后写入映射
+fn insert_text(mapping: &mut Mapping, key: &str, text: &str) {
+    if !text.trim().is_empty() {
+        mapping.insert(key.into(), Value::String(text.trim().to_string()));
+    }
+}
+
+/// 文本、数字和布尔值转换为文本，其他类型返回 None
+pub fn scalar_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(flag) => Some(flag.to_string()),
+        _ => None,
+    }
+}
+
+/// 单个文本或由文本组成的列表
+fn text_list(value: &Value) -> Option<Vec<String>> {
+    match value {
+        Value::Sequence(items) => items.iter().map(scalar_text).collect(),
+        other => scalar_text(other).map(|text| vec![text]),
+    }
+}
+
+/// 将面板中输入的文本转换为 YAML 值。`true`、`42` 等能原样还原的值保留原来的类型，
+/// 例如 `link-citations: true` 这类开关仍然是布尔值。
+fn text_value(text: &str) -> Value {
+    let text = text.trim();
+    match serde_yaml_ng::from_str::<Value>(text) {
+        Ok(value @ (Value::Bool(_) | Value::Number(_))) if scalar_text(&value).as_deref() == Some(text) => value,
+        _ => Value::String(text.to_string()),
+    }
+}
+
+/// 用新的 YAML 文本替换文档开头的元数据块；`yaml` 为 None 时删除元数据块
+pub fn replace_front_matter(text: &str, yaml: Option<&str>) -> String {
+    let existing = find_front_matter(text);
+    let body = existing.as_ref().map_or(text, |front_matter| &text[front_matter.end..]);
+    match yaml {
+        Some(yaml) if existing.is_some() => format!("---\n{}---\n{}", yaml, body),
+        Some(yaml) => format!("---\n{}---\n\n{}", yaml, body),
+        None if existing.is_some() => body.strip_prefix('\n').unwrap_or(body).to_string(),
+        None => body.to_string(),
+    }
+}
+
+/// 读取文档开头元数据中的各个字段，没有元数据或无法解析时返回 None
+pub fn front_matter_fields(text: &str) -> Option<Mapping> {
+    let front_matter = find_front_matter(text)?;
+    match serde_yaml_ng::from_str(front_matter.yaml) {
+        Ok(Value::Mapping(mapping)) => Some(mapping),
+        _ => None,
+    }
+}
+
+/// 检查文档开头的元数据能否解析，导出前调用以便给出明确的错误位置
+pub fn validate(text: &str) -> Result<(), YamlError> {
+    match find_front_matter(text) {
+        Some(front_matter) => DocumentMetadata::parse(front_matter.yaml, 2).map(|_| ()),
+        None => Ok(()),
+    }
+}
+
+/// 元数据面板的编辑状态。面板中的修改立即写回文档；
+/// 只有元数据块在编辑区中被修改或切换了文档时才重新解析，避免输入到一半的内容被改写。
+pub struct MetadataEditor {
+    document_id: u64,
+    /// 上次读取或写入的 YAML 文本
+    synced_yaml: Option<String>,
+    /// 与 `synced_yaml` 对应的字段，写回时只改写与它相比有变化的条目
+    synced_form: Option<DocumentMetadata>,
+    pub form: Result<DocumentMetadata, YamlError>,
+}
+
+impl MetadataEditor {
+    /// 返回与文档当前内容一致的编辑状态
+    pub fn sync<'a>(editor: &'a mut Option<MetadataEditor>, doc: &Document) -> &'a mut MetadataEditor {
+        let yaml = find_front_matter(&doc.markdown_text).map(|front_matter| front_matter.yaml.to_string());
+        let stale = editor.as_ref().is_none_or(|editor| {
+            editor.document_id != doc.id || editor.synced_yaml != yaml
+        });
+        if stale {
+            let form = match &yaml {
+                Some(yaml) => DocumentMetadata::parse(yaml, 2),
+                None => Ok(DocumentMetadata::default()),
+            };
+            *editor = Some(MetadataEditor {
+                document_id: doc.id,
+                synced_yaml: yaml,
+                synced_form: form.as_ref().ok().cloned(),
+                form,
+            });
+        }
+        editor.as_mut().expect("editor was just filled")
+    }
+
+    /// 将面板中的修改写回文档，只改写有变化的条目
+    pub fn write_back(&mut self, doc: &mut Document) {
+        let Ok(form) = &self.form else {
+            return;
+        };
+        let yaml = match (&self.synced_yaml, &self.synced_form) {
+            (Some(yaml), Some(previous)) => form.update_yaml(yaml, previous),
+            _ => form.to_yaml(),
+        };
+        doc.markdown_text = replace_front_matter(&doc.markdown_text, yaml.as_deref());
+        doc.is_dirty = true;
+        self.synced_yaml = find_front_matter(&doc.markdown_text).map(|front_matter| front_matter.yaml.to_string());
+        self.synced_form = Some(form.clone());
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_known_and_custom_fields() {
+        let yaml = "title: 年度报告\nauthor:\n- 张三\n- 李四\nkeywords: [预算, 审计]\nlang: zh-CN\ntoc: true\nheader-includes:\n- x\n";
+        let metadata = DocumentMetadata::parse(yaml, 2).unwrap();
+        assert_eq!(metadata.title, "年度报告");
+        assert_eq!(metadata.authors, vec!["张三", "李四"]);
+        assert_eq!(metadata.keywords, "预算, 审计");
+        assert_eq!(metadata.custom[0], CustomField { key: "lang".into(), value: CustomValue::Text("zh-CN".into()) });
+        assert_eq!(metadata.custom[1], CustomField { key: "toc".into(), value: CustomValue::Text("true".into()) });
+        assert!(matches!(metadata.custom[2].value, CustomValue::Structured(Value::Sequence(_))));
+    }
+
+    #[test]
+    fn to_yaml_round_trip() {
+        let metadata = DocumentMetadata::parse("title: 报告\nauthor: 张三\nlink-citations: true\ncount: 3\n", 2).unwrap();
+        let yaml = metadata.to_yaml().unwrap();
+        assert!(yaml.contains("link-citations: true\n"), "{}", yaml);
+        assert!(yaml.contains("count: 3\n"), "{}", yaml);
+        assert_eq!(DocumentMetadata::parse(&yaml, 2).unwrap(), metadata);
+        assert_eq!(DocumentMetadata::default().to_yaml(), None);
+    }
+
+    #[test]
+    fn reports_error_line_in_document() {
+        let error = DocumentMetadata::parse("title: a\nauthor: [b\n", 2).unwrap_err();
+        assert!(error.line.is_some_and(|line| line >= 3), "{:?}", error);
+    }
+
+    #[test]
+    fn update_yaml_keeps_unchanged_entries() {
+        let yaml = "# 封面\ntitle: '旧标题'\nabstract: |\n  第一段\n\n  第二段\nlang: zh-CN # 语言\n";
+        let previous = DocumentMetadata::parse(yaml, 2).unwrap();
+        let mut metadata = previous.clone();
+        metadata.title = "新标题".to_string();
+        metadata.date = "2024-01-01".to_string();
+
+        let updated = metadata.update_yaml(yaml, &previous).unwrap();
+        assert_eq!(
+            updated,
+            "# 封面\ntitle: 新标题\nabstract: |\n  第一段\n\n  第二段\nlang: zh-CN # 语言\ndate: 2024-01-01\n"
+        );
+        assert_eq!(DocumentMetadata::parse(&updated, 2).unwrap(), metadata);
+    }
+
+    #[test]
+    fn replaces_and_removes_front_matter() {
+        let text = "---\ntitle: a\n---\n正文\n";
+        assert_eq!(replace_front_matter(text, Some("title: b\n")), "---\ntitle: b\n---\n正文\n");
+        assert_eq!(replace_front_matter("正文\n", Some("title: b\n")), "---\ntitle: b\n---\n\n正文\n");
+        assert_eq!(replace_front_matter("---\ntitle: a\n---\n\n正文\n", None), "正文\n");
+    }
+}
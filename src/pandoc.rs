@@ -1,290 +1,311 @@
+use crate::export::{self, Citeproc, ExportFormat, ExportProfile};
+use crate::includes;
+use crate::jobs::{JobKind, JobOutcome, JobRequest, JOB_POLL_INTERVAL};
+use crate::metadata;
+use crate::pandoc_locator::PandocVersion;
 use crate::state::MyApp;
 use std::io::Write;
-use std::process::{Command, Stdio};
+use std::process::Command;
 use tempfile::Builder;
 
 impl MyApp {
-    pub fn check_for_task_result<T, F>(
-        receiver_option: &mut Option<crossbeam_channel::Receiver<Result<T, String>>>,
-        success_handler: F,
-    ) where
-        T: 'static,
-        F: FnOnce(T),
-    {
-        if let Some(receiver) = receiver_option 
-            && let Ok(result) = receiver.try_recv() {
-            match result {
-                Ok(value) => success_handler(value),
-                Err(error_message) => {
-                    rfd::MessageDialog::new()
-                        .set_level(rfd::MessageLevel::Error)
-                        .set_title("操作失败")
-                        .set_description(&error_message)
-                        .show();
+    /// 处理已结束的 pandoc 任务：导入结果在新标签页中打开，失败时弹出错误信息
+    pub fn check_job_results(&mut self, ctx: &egui::Context) {
+        for job in self.jobs.poll() {
+            self.record_job_log(&job);
+            match (job.kind, job.outcome) {
+                (_, JobOutcome::Cancelled) => {
+                    self.job_status_message = Some(format!("已取消：{}", job.description));
                 }
+                (_, JobOutcome::Failed) => {
+                    // 详细的错误信息记录在日志面板中，失败时日志面板会自动打开
+                    self.job_status_message = Some(format!("失败：{}", job.description));
+                }
+                (JobKind::Export { output_path }, JobOutcome::Succeeded { .. }) => {
+                    self.job_status_message = Some(format!("已导出 {}", output_path.display()));
+                }
+                (JobKind::Import(plan), JobOutcome::Succeeded { stdout }) => match String::from_utf8(stdout) {
+                    Ok(markdown_content) => {
+                        self.finish_import(markdown_content, plan);
+                        self.job_status_message = Some(format!("已完成：{}", job.description));
+                    }
+                    Err(e) => {
+                        self.job_status_message = Some(format!("失败：{}", job.description));
+                        self.open_info_dialog("导入失败", &format!("解析 Pandoc 输出失败: {}", e));
+                    }
+                },
             }
-            *receiver_option = None;
         }
-    }
 
-    pub fn check_for_conversion_result(&mut self) {
-        Self::check_for_task_result(&mut self.conversion_receiver, |success_message| {
-            rfd::MessageDialog::new()
-                .set_level(rfd::MessageLevel::Info)
-                .set_title("成功")
-                .set_description(&success_message)
-                .show();
-        });
-    }
-
-    pub fn check_for_import_result(&mut self) {
-        Self::check_for_task_result(&mut self.import_receiver, |markdown_content| {
-            self.markdown_text = markdown_content;
-            rfd::MessageDialog::new()
-                .set_level(rfd::MessageLevel::Info)
-                .set_title("成功")
-                .set_description("DOCX 文件已成功导入。")
-                .show();
-        });
+        if self.jobs.is_busy() {
+            ctx.request_repaint_after(JOB_POLL_INTERVAL);
+        }
     }
 
     pub fn set_reference_doc(&mut self) {
-        let handle = rfd::FileDialog::new()
+        let handle = self.file_dialog()
             .add_filter("Word 文档", &["docx"])
             .set_title("选择一个 DOCX 模板文件")
             .pick_file();
 
         if let Some(path) = handle {
-            match std::fs::read(&path) {
-                Ok(data) => {
-                    match docx_rs::read_docx(&data) {
-                        Ok(docx) => {
-                            self.paragraph_styles.clear();
-                            self.character_styles.clear();
-
-                            let mut default_style_ids = std::collections::HashSet::new();
-                            // Common default paragraph styles (style IDs can vary)
-                            let common_paragraph_styles = [
-                                "Normal", "Heading1", "Heading2", "Heading3", "Heading4",
-                                "Heading5", "Heading6", "Heading7", "Heading8", "Heading9",
-                                "Title", "Subtitle", "ListParagraph", "Caption",
-                                "TOC1", "TOC2", "TOC3", "TableNormal"
-                            ];
-                            
-                            // Common default character styles
-                            let common_character_styles = [
-                                "DefaultParagraphFont", "Emphasis", "Strong"
-                            ];
-
-                            for style in &common_paragraph_styles {
-                                default_style_ids.insert(style.to_string());
-                            }
-                            
-                            for style in &common_character_styles {
-                                default_style_ids.insert(style.to_string());
-                            }
-
-
-                            for s in docx.styles.styles {
-                                let name = &s.style_id;
-                                // 对于数字ID，我们保留原始ID作为标识符
-                                // 但在UI中显示时，我们可以添加样式类型的提示
-                                let display_name = name.clone();
-                                
-                                if !name.is_empty() && !default_style_ids.contains(name) {
-                                    match s.style_type {
-                                        docx_rs::StyleType::Paragraph => {
-                                            self.paragraph_styles.push(display_name);
-                                        }
-                                        docx_rs::StyleType::Character => {
-                                            self.character_styles.push(display_name);
-                                        }
-                                        _ => {}
-                                    }
-                                }
-                            }
-                            self.paragraph_styles.sort();
-                            self.character_styles.sort();
-                            self.reference_doc_path = Some(path);
+            self.remember_directory(&path);
+            self.use_reference_doc(path);
+        }
+    }
 
-                            rfd::MessageDialog::new()
-                                .set_level(rfd::MessageLevel::Info)
-                                .set_title("模板加载成功")
-                                .set_description(format!(
-                                    "成功加载模板，发现 {} 个段落样式和 {} 个字符样式。",
-                                    self.paragraph_styles.len(),
-                                    self.character_styles.len()
-                                ))
-                                .show();
-                        }
-                        Err(e) => {
-                            self.reference_doc_path = None;
-                            self.paragraph_styles.clear();
-                            self.character_styles.clear();
-                            rfd::MessageDialog::new()
-                                .set_level(rfd::MessageLevel::Error)
-                                .set_title("模板加载失败")
-                                .set_description(format!("无法解析DOCX文件: {:?}", e))
-                                .show();
-                        }
-                    }
-                }
-                Err(e) => {
-                    self.reference_doc_path = None;
-                    self.paragraph_styles.clear();
-                    self.character_styles.clear();
-                    rfd::MessageDialog::new()
-                        .set_level(rfd::MessageLevel::Error)
-                        .set_title("模板加载失败")
-                        .set_description(format!("无法读取文件: {}", e))
-                        .show();
-                }
+    /// 加载指定的参考文档，并用消息框告知结果
+    pub fn use_reference_doc(&mut self, path: std::path::PathBuf) {
+        match self.load_reference_doc(path) {
+            Ok(()) => {
+                rfd::MessageDialog::new()
+                    .set_level(rfd::MessageLevel::Info)
+                    .set_title("模板加载成功")
+                    .set_description(format!(
+                        "成功加载模板，发现 {} 个段落样式和 {} 个字符样式。",
+                        self.paragraph_styles.len(),
+                        self.character_styles.len()
+                    ))
+                    .show();
+            }
+            Err(error_message) => {
+                self.prune_recent_lists();
+                rfd::MessageDialog::new()
+                    .set_level(rfd::MessageLevel::Error)
+                    .set_title("模板加载失败")
+                    .set_description(&error_message)
+                    .show();
             }
         }
     }
 
-    pub fn import_from_docx(&mut self) {
-        if self.import_receiver.is_some() || self.conversion_receiver.is_some() {
-            rfd::MessageDialog::new()
-                .set_level(rfd::MessageLevel::Warning)
-                .set_title("请稍候")
-                .set_description("当前有另一个文件操作任务正在进行中。")
-                .show();
-            return;
-        }
+    /// 读取指定的 DOCX 文件作为导出模板，并解析其中的自定义段落和字符样式。
+    /// 失败时会清除当前模板。
+    pub fn load_reference_doc(&mut self, path: std::path::PathBuf) -> Result<(), String> {
+        self.reference_doc_path = None;
+        self.paragraph_styles.clear();
+        self.character_styles.clear();
 
-        let input_path = match rfd::FileDialog::new()
-            .add_filter("Word 文档", &["docx"])
-            .pick_file() {
-            Some(path) => path,
-            None => return,
-        };
+        let data = std::fs::read(&path).map_err(|e| format!("无法读取文件: {}", e))?;
+        let docx = docx_rs::read_docx(&data).map_err(|e| format!("无法解析DOCX文件: {:?}", e))?;
 
-        let (sender, receiver) = crossbeam_channel::unbounded();
-        self.import_receiver = Some(receiver);
+        let mut default_style_ids = std::collections::HashSet::new();
+        // Common default paragraph styles (style IDs can vary)
+        let common_paragraph_styles = [
+            "Normal", "Heading1", "Heading2", "Heading3", "Heading4",
+            "Heading5", "Heading6", "Heading7", "Heading8", "Heading9",
+            "Title", "Subtitle", "ListParagraph", "Caption",
+            "TOC1", "TOC2", "TOC3", "TableNormal"
+        ];
 
-        std::thread::spawn(move || {
-            let pandoc_executable_name = if cfg!(target_os = "windows") { "pandoc.exe" } else { "pandoc" };
-            let pandoc_path = std::env::current_exe()
-                .ok()
-                .and_then(|p| p.parent().map(|p| p.join(pandoc_executable_name)))
-                .filter(|p| p.exists())
-                .map(|p| p.to_string_lossy().into_owned())
-                .unwrap_or_else(|| "pandoc".to_string());
+        // Common default character styles
+        let common_character_styles = [
+            "DefaultParagraphFont", "Emphasis", "Strong"
+        ];
+
+        for style in &common_paragraph_styles {
+            default_style_ids.insert(style.to_string());
+        }
+
+        for style in &common_character_styles {
+            default_style_ids.insert(style.to_string());
+        }
 
-            let pandoc_output = Command::new(pandoc_path)
-                .arg(&input_path)
-                .arg("-f")
-                .arg("docx")
-                .arg("-t")
-                .arg("markdown")
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped())
-                .output();
+        for s in docx.styles.styles {
+            let name = &s.style_id;
+            // 对于数字ID，我们保留原始ID作为标识符
+            // 但在UI中显示时，我们可以添加样式类型的提示
+            let display_name = name.clone();
 
-            let result = match pandoc_output {
-                Ok(output) => {
-                    if output.status.success() {
-                        String::from_utf8(output.stdout)
-                            .map_err(|e| format!("解析 Pandoc 输出失败: {}", e))
-                    } else {
-                        let error_message = String::from_utf8_lossy(&output.stderr);
-                        Err(format!("Pandoc 转换失败:\n{}", error_message))
+            if !name.is_empty() && !default_style_ids.contains(name) {
+                match s.style_type {
+                    docx_rs::StyleType::Paragraph => {
+                        self.paragraph_styles.push(display_name);
                     }
+                    docx_rs::StyleType::Character => {
+                        self.character_styles.push(display_name);
+                    }
+                    _ => {}
                 }
-                Err(e) => {
-                    Err(format!("无法执行 Pandoc 命令。\n请确保 Pandoc 已正确安装。\n\n错误详情: {}", e))
-                }
-            };
-            
-            let _ = sender.send(result);
-        });
-    }
-
-    pub fn export_as_docx(&mut self) {
-        if self.conversion_receiver.is_some() {
-            rfd::MessageDialog::new()
-                .set_level(rfd::MessageLevel::Warning)
-                .set_title("请稍候")
-                .set_description("上一个转换任务仍在进行中。")
-                .show();
-            return;
+            }
         }
+        self.paragraph_styles.sort();
+        self.character_styles.sort();
+        self.remember_recent_template(&path);
+        self.reference_doc_path = Some(path);
+        Ok(())
+    }
 
-        // 获取软件所在目录作为默认目录
-        let current_dir = std::env::current_exe()
-            .ok()
-            .and_then(|p| p.parent().map(|p| p.to_path_buf()))
-            .unwrap_or_else(|| std::path::PathBuf::from("."));
+    /// 按指定的导出方案导出当前文档
+    pub fn export_document(&mut self, profile: ExportProfile) {
+        let format = profile.format;
+        // 优先使用最近用过的文件夹，否则使用软件所在目录作为默认目录
+        let current_dir = self.recent_directories.first().cloned().unwrap_or_else(|| {
+            std::env::current_exe()
+                .ok()
+                .and_then(|p| p.parent().map(|p| p.to_path_buf()))
+                .unwrap_or_else(|| std::path::PathBuf::from("."))
+        });
 
         // 设置默认文件名
-        let default_file_name = "out01.docx";
+        let default_file_name = format!("out01.{}", format.extension());
 
         let output_path = match rfd::FileDialog::new()
-            .add_filter("Word 文档", &["docx"])
+            .add_filter(format.label(), &[format.extension()])
             .set_directory(&current_dir)
             .set_file_name(default_file_name)
             .save_file() {
             Some(path) => path,
             None => return,
         };
+        self.remember_directory(&output_path);
 
-        let (sender, receiver) = crossbeam_channel::unbounded();
-        self.conversion_receiver = Some(receiver);
-        let markdown_content = self.markdown_text.clone();
-        let reference_doc = self.reference_doc_path.clone();
+        let Some((markdown_content, resource_dirs)) = self.prepare_document_export() else {
+            return;
+        };
+        let markdown_content = self.substitute_document_variables(markdown_content);
+        let source_document = Some(self.doc().id);
+        self.queue_export(markdown_content, output_path, &profile, resource_dirs, source_document);
+    }
 
-        std::thread::spawn(move || {
-            let mut temp_file = match Builder::new().prefix("pandoc_input").suffix(".md").tempfile() {
-                Ok(file) => file,
-                Err(e) => {
-                    let _ = sender.send(Err(format!("无法创建临时文件: {}", e)));
-                    return;
-                }
-            };
+    /// 检查当前文档的元数据并展开包含指令，返回要导出的 Markdown 和图片的查找目录。
+    /// 有错误时显示提示并返回 None。
+    pub fn prepare_document_export(&mut self) -> Option<(String, Vec<std::path::PathBuf>)> {
+        // 元数据决定标题块和 DOCX 文档属性，有语法错误时先让用户修正，而不是得到 pandoc 的报错
+        if let Err(error) = metadata::validate(&self.doc().markdown_text) {
+            self.metadata_panel_open = true;
+            self.open_info_dialog("导出失败", &format!("文档开头的 YAML 元数据有误：\n\n{}", error));
+            return None;
+        }
+        let doc = self.doc();
+        let expanded = includes::expand_includes(&doc.markdown_text, doc.file_path.as_deref());
+        if !expanded.errors.is_empty() {
+            let details: Vec<String> = expanded.errors.iter().map(|e| e.to_string()).collect();
+            self.open_info_dialog("导出失败", &format!("以下包含指令无法展开：\n\n{}", details.join("\n")));
+            return None;
+        }
+        // 让 pandoc 能够找到相对于文档所在目录的图片
+        let resource_dirs = self.doc()
+            .base_dir()
+            .map(|dir| vec![dir.to_path_buf()])
+            .unwrap_or_default();
+        Some((expanded.text, resource_dirs))
+    }
 
-            if temp_file.write_all(markdown_content.as_bytes()).is_err() {
-                let _ = sender.send(Err("无法写入临时文件。".to_string()));
-                return;
+    /// 将 Markdown 内容写入临时文件，并按导出方案把导出任务加入队列。
+    /// 方案没有为 DOCX 指定参考文档时，使用“设置导出模板”选择的模板。
+    /// 导出前的检查失败时显示提示并返回 false。
+    pub fn queue_export(
+        &mut self,
+        markdown_content: String,
+        output_path: std::path::PathBuf,
+        profile: &ExportProfile,
+        resource_dirs: Vec<std::path::PathBuf>,
+        source_document: Option<u64>,
+    ) -> bool {
+        let format = profile.format;
+        // 提前检查 PDF 引擎，避免等到 pandoc 运行后才得到难以理解的错误。
+        // 只缓存检查通过的引擎，用户安装引擎后无需重启即可导出。
+        if format == ExportFormat::Pdf && !self.available_pdf_engines.contains(&profile.pdf_engine) {
+            if let Err(error_message) = export::check_pdf_engine(&profile.pdf_engine) {
+                self.open_info_dialog("导出失败", &error_message);
+                return false;
             }
+            self.available_pdf_engines.insert(profile.pdf_engine.clone());
+        }
 
-            let pandoc_executable_name = if cfg!(target_os = "windows") { "pandoc.exe" } else { "pandoc" };
-            let pandoc_path = std::env::current_exe()
-                .ok()
-                .and_then(|p| p.parent().map(|p| p.join(pandoc_executable_name)))
-                .filter(|p| p.exists())
-                .map(|p| p.to_string_lossy().into_owned())
-                .unwrap_or_else(|| "pandoc".to_string());
-
-            let mut command = Command::new(pandoc_path);
-            
-            command.arg(temp_file.path())
-                   .arg("-o")
-                   .arg(&output_path);
+        // `[TOC]` 标记处插入目录，无法插入时改为由 pandoc 在开头生成
+        let (markdown_content, pandoc_toc) = profile.place_toc(&markdown_content);
 
-            if let Some(ref_path) = reference_doc {
-                command.arg("--reference-doc").arg(ref_path);
+        let mut temp_file = match Builder::new().prefix("pandoc_input").suffix(".md").tempfile() {
+            Ok(file) => file,
+            Err(e) => {
+                self.open_info_dialog("导出失败", &format!("无法创建临时文件: {}", e));
+                return false;
             }
+        };
+
+        if temp_file.write_all(markdown_content.as_bytes()).is_err() {
+            self.open_info_dialog("导出失败", "无法写入临时文件。");
+            return false;
+        }
 
-            let pandoc_output = command.stdout(Stdio::piped())
-                                     .stderr(Stdio::piped())
-                                     .output();
+        let mut command = Command::new(&self.pandoc.executable);
+        command.arg(temp_file.path())
+               .arg("-o")
+               .arg(&output_path);
 
-            let result = match pandoc_output {
-                Ok(output) => {
-                    if output.status.success() {
-                        Ok("文件已成功导出为 DOCX。".to_string())
-                    } else {
-                        let error_message = String::from_utf8_lossy(&output.stderr);
-                        Err(format!("Pandoc 转换失败:\n{}", error_message))
-                    }
+        if let Some(writer) = format.pandoc_writer() {
+            command.arg("-t").arg(writer);
+        }
+
+        let reference_doc = match format {
+            ExportFormat::Docx => profile.reference_doc.clone().or_else(|| self.reference_doc_path.clone()),
+            ExportFormat::Odt => profile.reference_doc.clone(),
+            _ => None,
+        };
+        if let Some(ref_path) = reference_doc {
+            command.arg("--reference-doc").arg(ref_path);
+        }
+
+        match format {
+            ExportFormat::Html => {
+                command.arg("--standalone");
+                if let Some(css) = &profile.html_css {
+                    command.arg("--css").arg(css);
                 }
-                Err(e) => {
-                    Err(format!("无法执行 Pandoc 命令。\n请确保 Pandoc 已正确安装并位于系统 PATH 中，或与本程序在同一目录下。\n\n错误详情: {}", e))
+                if profile.html_embed_resources {
+                    // pandoc 2.19 起 `--self-contained` 被 `--embed-resources` 取代
+                    let embed_supported = self.pandoc.version
+                        .as_ref()
+                        .is_ok_and(|v| *v >= PandocVersion { major: 2, minor: 19, patch: 0 });
+                    command.arg(if embed_supported { "--embed-resources" } else { "--self-contained" });
                 }
-            };
-            
-            let _ = sender.send(result);
+            }
+            ExportFormat::Pdf => {
+                command.arg("--pdf-engine").arg(&profile.pdf_engine);
+                if profile.pdf_engine_is_latex() && !profile.pdf_cjk_font.trim().is_empty() {
+                    command.arg("-V").arg(format!("CJKmainfont={}", profile.pdf_cjk_font.trim()));
+                }
+            }
+            _ => {}
+        }
+
+        if !resource_dirs.is_empty()
+            && let Ok(resource_path) = std::env::join_paths(&resource_dirs) {
+            command.arg("--resource-path").arg(resource_path);
+        }
+        // 元数据中 `bibliography`、`csl` 等相对路径以文档所在目录为准
+        if let Some(dir) = resource_dirs.first() {
+            command.current_dir(dir);
+        }
+
+        let citeproc = if !profile.uses_citations(&markdown_content) {
+            Citeproc::None
+        } else if self.pandoc.version.as_ref().is_ok_and(|v| *v >= PandocVersion { major: 2, minor: 11, patch: 0 }) {
+            Citeproc::Builtin
+        } else {
+            Citeproc::Filter
+        };
+        if let Err(error_message) = profile.apply_common_args(&mut command, pandoc_toc, citeproc) {
+            self.open_info_dialog("导出失败", &error_message);
+            return false;
+        }
+
+        self.jobs.enqueue(JobRequest {
+            description: format!("导出 {}", display_file_name(&output_path)),
+            kind: JobKind::Export { output_path },
+            command,
+            temp_input: Some(temp_file),
+            source_document,
         });
+        true
     }
 }
+
+/// 任务说明中显示的文件名
+fn display_file_name(path: &std::path::Path) -> String {
+    path.file_name()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
@@ -0,0 +1,127 @@
+use crate::state::MyApp;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// 导出 DOCX 时 `custom-style` 自定义样式和 `--resource-path` 所需的最低 Pandoc 版本
+pub const MIN_SUPPORTED_VERSION: PandocVersion = PandocVersion { major: 2, minor: 0, patch: 0 };
+
+/// Pandoc 的版本号，只比较前三段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PandocVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl PandocVersion {
+    /// 从 `pandoc --version` 输出的第一行（例如 `pandoc 3.1.9`）解析版本号
+    pub fn parse(output: &str) -> Option<Self> {
+        let first_line = output.lines().next()?;
+        let version = first_line.split_whitespace().nth(1)?;
+        let mut parts = version.split('.').map(|part| part.parse::<u32>().ok());
+        Some(Self {
+            major: parts.next()??,
+            minor: parts.next().flatten().unwrap_or(0),
+            patch: parts.next().flatten().unwrap_or(0),
+        })
+    }
+}
+
+impl std::fmt::Display for PandocVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// 当前使用的 Pandoc 程序及检测到的版本
+#[derive(Debug, Clone)]
+pub struct PandocInfo {
+    pub executable: PathBuf,
+    /// 运行 `pandoc --version` 的结果，失败时为错误说明
+    pub version: Result<PandocVersion, String>,
+}
+
+impl PandocInfo {
+    /// 确定要使用的 Pandoc 程序并检测其版本。
+    /// 优先使用用户设置的路径，其次是本程序所在目录下的 pandoc，最后是 PATH 中的 pandoc。
+    pub fn detect(custom_path: Option<&Path>) -> Self {
+        let executable = custom_path
+            .map(Path::to_path_buf)
+            .unwrap_or_else(default_executable);
+        let version = detect_version(&executable);
+        Self { executable, version }
+    }
+
+    /// 需要提醒用户的问题：找不到 Pandoc，或版本过旧
+    pub fn warning(&self) -> Option<String> {
+        match &self.version {
+            Err(error_message) => Some(format!(
+                "未能找到可用的 Pandoc（{}）：\n{}\n\n导入和导出 DOCX 需要 Pandoc。请安装 Pandoc，或在“工具”菜单中设置 Pandoc 的路径。",
+                self.executable.display(),
+                error_message
+            )),
+            Ok(version) if *version < MIN_SUPPORTED_VERSION => Some(format!(
+                "当前 Pandoc 版本为 {}，低于 {}。\n自定义样式（custom-style）等功能可能无法正常工作，建议升级 Pandoc。",
+                version, MIN_SUPPORTED_VERSION
+            )),
+            Ok(_) => None,
+        }
+    }
+
+    /// “关于”窗口中显示的简短说明
+    pub fn summary(&self) -> String {
+        match &self.version {
+            Ok(version) => format!("Pandoc 版本: {}", version),
+            Err(_) => "未找到 Pandoc".to_string(),
+        }
+    }
+}
+
+/// 没有设置自定义路径时使用的 Pandoc：本程序所在目录下的 pandoc，否则为 PATH 中的 pandoc
+pub fn default_executable() -> PathBuf {
+    let pandoc_executable_name = if cfg!(target_os = "windows") { "pandoc.exe" } else { "pandoc" };
+    std::env::current_exe()
+        .ok()
+        .and_then(|p| p.parent().map(|p| p.join(pandoc_executable_name)))
+        .filter(|p| p.exists())
+        .unwrap_or_else(|| PathBuf::from("pandoc"))
+}
+
+fn detect_version(executable: &Path) -> Result<PandocVersion, String> {
+    let output = Command::new(executable)
+        .arg("--version")
+        .output()
+        .map_err(|e| format!("无法执行 Pandoc 命令：{}", e))?;
+    if !output.status.success() {
+        return Err(format!("Pandoc 返回错误：{}", String::from_utf8_lossy(&output.stderr)));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    PandocVersion::parse(&stdout).ok_or_else(|| "无法识别 Pandoc 的版本信息。".to_string())
+}
+
+impl MyApp {
+    /// 选择 Pandoc 程序的位置
+    pub fn choose_pandoc_path(&mut self) {
+        let handle = rfd::FileDialog::new()
+            .set_title("选择 Pandoc 程序")
+            .pick_file();
+
+        if let Some(path) = handle {
+            self.set_pandoc_path(Some(path));
+        }
+    }
+
+    /// 设置自定义 Pandoc 路径（None 表示恢复默认查找方式），重新检测版本并告知结果
+    pub fn set_pandoc_path(&mut self, path: Option<PathBuf>) {
+        self.pandoc = PandocInfo::detect(path.as_deref());
+        self.custom_pandoc_path = path;
+
+        match self.pandoc.warning() {
+            Some(warning) => self.open_info_dialog("Pandoc 检测", &warning),
+            None => self.open_info_dialog(
+                "Pandoc 检测",
+                &format!("{}\n{}", self.pandoc.summary(), self.pandoc.executable.display()),
+            ),
+        }
+    }
+}
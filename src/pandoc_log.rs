@@ -0,0 +1,239 @@
+use crate::includes;
+use crate::jobs::{FinishedJob, JobOutcome};
+use crate::state::MyApp;
+use regex::Regex;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// 日志面板中最多保留的记录数量
+const MAX_LOG_ENTRIES: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticLevel {
+    Info,
+    Warning,
+    Error,
+}
+
+impl DiagnosticLevel {
+    pub fn label(&self) -> &'static str {
+        match self {
+            DiagnosticLevel::Info => "信息",
+            DiagnosticLevel::Warning => "警告",
+            DiagnosticLevel::Error => "错误",
+        }
+    }
+}
+
+/// 一次 pandoc 调用的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogStatus {
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl LogStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            LogStatus::Succeeded => "成功",
+            LogStatus::Failed => "失败",
+            LogStatus::Cancelled => "已取消",
+        }
+    }
+}
+
+/// 信息来自哪个过滤器文件的哪一行
+#[derive(Debug, Clone)]
+pub struct FilterLocation {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+}
+
+/// pandoc 输出中的一条信息
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub level: DiagnosticLevel,
+    pub message: String,
+    /// 信息对应的源文档行号（从 1 开始），无法定位时为 None
+    pub line: Option<usize>,
+    /// 过滤器出错时，出错的过滤器文件和行号
+    pub filter: Option<FilterLocation>,
+}
+
+/// 一次 pandoc 调用的记录
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub description: String,
+    pub status: LogStatus,
+    pub command_line: String,
+    pub duration: Duration,
+    pub exit_code: Option<i32>,
+    pub diagnostics: Vec<Diagnostic>,
+    /// pandoc 原始的错误输出
+    pub stderr: String,
+    /// 任务内容来自哪个文档，用于跳转到对应的行
+    pub source_document: Option<u64>,
+}
+
+impl LogEntry {
+    pub fn has_problems(&self) -> bool {
+        self.status != LogStatus::Succeeded || self.diagnostics.iter().any(|d| d.level != DiagnosticLevel::Info)
+    }
+}
+
+/// 将 pandoc 的错误输出拆分为单条信息。
+/// `[WARNING]`、`[INFO]` 等前缀开头的行开始一条新信息，缩进的行属于上一条信息，
+/// 其余的行（例如 `pandoc: ...`）视为错误。过滤器出错时，之后的 Lua 错误信息和调用栈
+/// 都归入 `Error running filter ...` 这一条信息，并记录出错的过滤器文件和行号。
+pub fn parse_diagnostics(stderr: &str, source_text: Option<&str>) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let mut in_filter_error = false;
+    for line in stderr.lines() {
+        if line.trim().is_empty() {
+            in_filter_error = false;
+            continue;
+        }
+        if in_filter_error
+            && !line.starts_with('[')
+            && let Some(last) = diagnostics.last_mut() {
+            last.message.push('\n');
+            last.message.push_str(line.trim());
+            continue;
+        }
+        in_filter_error = line.starts_with("Error running filter") || line.starts_with("Error running Lua");
+
+        let (level, message) = if let Some(rest) = line.strip_prefix("[WARNING]") {
+            (DiagnosticLevel::Warning, rest)
+        } else if let Some(rest) = line.strip_prefix("[INFO]") {
+            (DiagnosticLevel::Info, rest)
+        } else if let Some(rest) = line.strip_prefix("[ERROR]") {
+            (DiagnosticLevel::Error, rest)
+        } else if line.starts_with(char::is_whitespace)
+            && let Some(last) = diagnostics.last_mut() {
+            last.message.push('\n');
+            last.message.push_str(line.trim());
+            continue;
+        } else {
+            (DiagnosticLevel::Error, line)
+        };
+
+        diagnostics.push(Diagnostic {
+            level,
+            message: message.trim().to_string(),
+            line: None,
+            filter: None,
+        });
+    }
+
+    for diagnostic in &mut diagnostics {
+        diagnostic.filter = locate_filter(&diagnostic.message);
+        // 过滤器的错误与源文档的内容无关
+        if diagnostic.filter.is_none()
+            && let Some(source_text) = source_text {
+            diagnostic.line = locate_line(&diagnostic.message, source_text);
+        }
+    }
+    diagnostics
+}
+
+/// 从信息中找出出错的过滤器：`Error running filter 路径:` 给出文件，
+/// Lua 错误中的 `文件.lua:行号:` 给出行号
+fn locate_filter(message: &str) -> Option<FilterLocation> {
+    let running = Regex::new(r"^Error running filter (.+?):?$").unwrap();
+    let lua_position = Regex::new(r#"((?:[A-Za-z]:)?[^\s:'"\[\]]*\.lua):(\d+):"#).unwrap();
+
+    let first_line = message.lines().next().unwrap_or_default();
+    let filter_path = running.captures(first_line).map(|caps| PathBuf::from(caps[1].trim()));
+    let position = lua_position.captures(message)
+        .map(|caps| (PathBuf::from(&caps[1]), caps[2].parse::<usize>().ok()));
+
+    match (filter_path, position) {
+        (Some(path), Some((lua_path, line))) => {
+            // 错误可能发生在过滤器加载的其他 Lua 模块中，此时行号不属于该过滤器
+            let same_file = lua_path.file_name() == path.file_name();
+            Some(FilterLocation { path, line: if same_file { line } else { None } })
+        }
+        (Some(path), None) => Some(FilterLocation { path, line: None }),
+        (None, Some((path, line))) => Some(FilterLocation { path, line }),
+        (None, None) => None,
+    }
+}
+
+/// 在源文档中查找信息所指的行：优先查找信息中引用的内容（例如图片路径），
+/// 其次使用信息中给出的行号（文档中有包含指令时行号不可靠，不使用）。
+fn locate_line(message: &str, source_text: &str) -> Option<usize> {
+    let quoted = Regex::new(r"'([^']+)'").unwrap();
+    for capture in quoted.captures_iter(message) {
+        let needle = &capture[1];
+        if let Some(index) = source_text.lines().position(|line| line.contains(needle)) {
+            return Some(index + 1);
+        }
+    }
+
+    if includes::has_include_directives(source_text) {
+        return None;
+    }
+    let line_number = Regex::new(r"line (\d+)").unwrap();
+    line_number
+        .captures(message)
+        .and_then(|capture| capture[1].parse::<usize>().ok())
+        .filter(|&line| line >= 1 && line <= source_text.lines().count())
+}
+
+impl MyApp {
+    /// 记录一次已结束的 pandoc 调用；出现问题时自动打开日志面板
+    pub fn record_job_log(&mut self, job: &FinishedJob) {
+        let source_text = job.source_document
+            .and_then(|id| self.document_index(id))
+            .map(|index| self.documents[index].markdown_text.as_str());
+
+        let status = match job.outcome {
+            JobOutcome::Succeeded { .. } => LogStatus::Succeeded,
+            JobOutcome::Failed => LogStatus::Failed,
+            JobOutcome::Cancelled => LogStatus::Cancelled,
+        };
+        let entry = LogEntry {
+            description: job.description.clone(),
+            status,
+            command_line: job.command_line.clone(),
+            duration: job.duration,
+            exit_code: job.exit_code,
+            diagnostics: parse_diagnostics(&job.stderr, source_text),
+            stderr: job.stderr.clone(),
+            source_document: job.source_document,
+        };
+
+        if status == LogStatus::Failed {
+            self.log_panel_open = true;
+        }
+        self.pandoc_log.push(entry);
+        if self.pandoc_log.len() > MAX_LOG_ENTRIES {
+            self.pandoc_log.remove(0);
+        }
+    }
+
+    /// 在标签页中打开出错的过滤器文件，并跳转到出错的行
+    pub fn open_filter_location(&mut self, location: &FilterLocation) {
+        self.open_file_from_path(location.path.clone());
+        let opened = self.documents.iter().find(|doc| doc.file_path.as_ref() == Some(&location.path));
+        if let (Some(doc), Some(line)) = (opened, location.line) {
+            self.jump_to_line(doc.id, line);
+        }
+    }
+
+    /// 切换到指定文档，并将光标移动到指定行的开头
+    pub fn jump_to_line(&mut self, document_id: u64, line: usize) {
+        let Some(index) = self.document_index(document_id) else {
+            return;
+        };
+        self.active_document = index;
+        let doc = &mut self.documents[index];
+        let char_index: usize = doc.markdown_text
+            .split_inclusive('\n')
+            .take(line.saturating_sub(1))
+            .map(|l| l.chars().count())
+            .sum();
+        doc.pending_cursor = Some(char_index);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::app;
+use crate::includes::{self, ExpandedText};
+use crate::metadata;
+use regex::Regex;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// 预览中的处理结果在多长时间后重新读取被包含的文件
+const PREVIEW_REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// 预览区缓存的处理结果：替换模板变量（保留占位符时），去掉元数据块，展开包含指令，
+/// 并将相对路径的图片解析为绝对路径
+pub struct PreviewCache {
+    source_hash: u64,
+    prepared_at: Instant,
+    pub prepared: ExpandedText,
+}
+
+impl PreviewCache {
+    /// 文本是否需要处理后再预览；普通文档直接渲染原文，避免多余的开销
+    pub fn needs_preparation(text: &str, base_dir: Option<&Path>) -> bool {
+        includes::has_include_directives(text)
+            || (base_dir.is_some() && text.contains("!["))
+            || metadata::find_front_matter(text).is_some()
+    }
+
+    /// 返回预览使用的处理结果；文本变化或缓存过期时重新处理
+    pub fn refresh<'a>(
+        cache: &'a mut Option<PreviewCache>,
+        text: &str,
+        variables: Option<&HashMap<String, String>>,
+        source_file: Option<&Path>,
+        base_dir: Option<&Path>,
+    ) -> &'a ExpandedText {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        text.hash(&mut hasher);
+        if let Some(variables) = variables {
+            let mut sorted: Vec<_> = variables.iter().collect();
+            sorted.sort();
+            sorted.hash(&mut hasher);
+        }
+        source_file.hash(&mut hasher);
+        base_dir.hash(&mut hasher);
+        let source_hash = hasher.finish();
+
+        let stale = cache.as_ref().is_none_or(|preview| {
+            preview.source_hash != source_hash || preview.prepared_at.elapsed() > PREVIEW_REFRESH_INTERVAL
+        });
+        if stale {
+            let substituted = variables.map(|variables| app::substitute_markers(text, variables));
+            let text = substituted.as_deref().unwrap_or(text);
+            // 元数据块由文档属性面板编辑，不在预览中显示
+            let body = metadata::find_front_matter(text).map_or(text, |front_matter| &text[front_matter.end..]);
+            let mut prepared = includes::expand_includes(body, source_file);
+            if let Some(base_dir) = base_dir {
+                prepared.text = resolve_image_links(&prepared.text, base_dir);
+            }
+            *cache = Some(PreviewCache {
+                source_hash,
+                prepared_at: Instant::now(),
+                prepared,
+            });
+        }
+        &cache.as_ref().expect("cache was just filled").prepared
+    }
+}
+
+/// 将 Markdown 中相对路径的图片改写为基于 `base_dir` 的 `file://` 地址，
+/// 使预览区能够找到与文档放在一起的图片。绝对路径和带协议的地址保持不变。
+pub fn resolve_image_links(text: &str, base_dir: &Path) -> String {
+    let image_link = Regex::new(r"(!\[[^\]]*\]\(<?)([^)>\s]+)").unwrap();
+    image_link
+        .replace_all(text, |caps: &regex::Captures| {
+            let target = &caps[2];
+            let is_relative = !target.contains("://")
+                && !target.starts_with("data:")
+                && !target.starts_with('/')
+                && !Path::new(target).is_absolute();
+            if is_relative {
+                format!("{}file://{}", &caps[1], base_dir.join(target).display())
+            } else {
+                caps[0].to_string()
+            }
+        })
+        .into_owned()
+}
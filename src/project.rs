@@ -0,0 +1,359 @@
+use crate::app;
+use crate::includes;
+use crate::metadata;
+use crate::state::MyApp;
+use crate::variables;
+use crate::watcher;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// 新建项目时使用的默认清单文件名
+pub const DEFAULT_MANIFEST_NAME: &str = "rustf.json";
+
+/// 项目导出选项
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProjectExportOptions {
+    /// 默认的导出文件路径，为空时导出前会询问
+    pub output_path: Option<PathBuf>,
+}
+
+/// 项目清单：按顺序列出组成一份文档的各章节文件，以及导出时使用的模板和变量。
+/// 清单中的路径相对于清单文件所在目录保存。
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProjectManifest {
+    pub name: String,
+    pub chapters: Vec<PathBuf>,
+    pub reference_doc: Option<PathBuf>,
+    /// 导出时应用的模板变量文件，按扩展名读取 JSON、YAML、CSV 或 `名称=值` 格式
+    pub variables_file: Option<PathBuf>,
+    pub export: ProjectExportOptions,
+}
+
+/// 已打开的项目
+pub struct Project {
+    pub manifest_path: PathBuf,
+    pub manifest: ProjectManifest,
+    /// 清单是否有尚未写回磁盘的修改
+    pub is_dirty: bool,
+    /// 最近一次读取或写入清单时文件的修改时间
+    pub disk_modified: Option<SystemTime>,
+    /// 最近一次写入清单失败的原因，自动保存时同样的错误只提示一次
+    pub save_error: Option<String>,
+}
+
+impl Project {
+    pub fn new(manifest_path: PathBuf) -> Self {
+        let name = manifest_path
+            .parent()
+            .and_then(|dir| dir.file_name())
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "未命名项目".to_string());
+        Self {
+            manifest_path,
+            manifest: ProjectManifest { name, ..Default::default() },
+            is_dirty: true,
+            disk_modified: None,
+            save_error: None,
+        }
+    }
+
+    pub fn load(manifest_path: PathBuf) -> Result<Self, String> {
+        let json = std::fs::read_to_string(&manifest_path)
+            .map_err(|e| format!("无法读取项目文件 {}：{}", manifest_path.display(), e))?;
+        let manifest = serde_json::from_str(&json)
+            .map_err(|e| format!("项目文件格式错误：{}", e))?;
+        let disk_modified = watcher::modified_time(&manifest_path);
+        Ok(Self { manifest_path, manifest, is_dirty: false, disk_modified, save_error: None })
+    }
+
+    pub fn save(&mut self) -> Result<(), String> {
+        let result = self.write_manifest();
+        self.save_error = result.as_ref().err().cloned();
+        result
+    }
+
+    fn write_manifest(&mut self) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(&self.manifest)
+            .map_err(|e| format!("无法序列化项目文件：{}", e))?;
+        std::fs::write(&self.manifest_path, json)
+            .map_err(|e| format!("无法写入项目文件 {}：{}", self.manifest_path.display(), e))?;
+        self.is_dirty = false;
+        self.disk_modified = watcher::modified_time(&self.manifest_path);
+        Ok(())
+    }
+
+    /// 清单文件所在目录，清单中的相对路径以此为基准
+    pub fn base_dir(&self) -> &Path {
+        self.manifest_path.parent().unwrap_or(Path::new("."))
+    }
+
+    /// 将清单中的路径解析为绝对路径
+    pub fn resolve(&self, path: &Path) -> PathBuf {
+        if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            self.base_dir().join(path)
+        }
+    }
+
+    /// 将路径转换为相对于清单目录的形式；不在该目录下的路径保持原样
+    pub fn relativize(&self, path: &Path) -> PathBuf {
+        path.strip_prefix(self.base_dir())
+            .map(|p| p.to_path_buf())
+            .unwrap_or_else(|_| path.to_path_buf())
+    }
+
+    pub fn chapter_paths(&self) -> Vec<PathBuf> {
+        self.manifest.chapters.iter().map(|p| self.resolve(p)).collect()
+    }
+
+    pub fn add_chapter(&mut self, path: &Path) {
+        let relative = self.relativize(path);
+        if !self.manifest.chapters.contains(&relative) {
+            self.manifest.chapters.push(relative);
+            self.is_dirty = true;
+        }
+    }
+
+    pub fn move_chapter(&mut self, index: usize, offset: isize) {
+        let target = index as isize + offset;
+        if target < 0 || target as usize >= self.manifest.chapters.len() {
+            return;
+        }
+        self.manifest.chapters.swap(index, target as usize);
+        self.is_dirty = true;
+    }
+
+    pub fn remove_chapter(&mut self, index: usize) {
+        if index < self.manifest.chapters.len() {
+            self.manifest.chapters.remove(index);
+            self.is_dirty = true;
+        }
+    }
+}
+
+impl MyApp {
+    pub fn new_project(&mut self) {
+        let handle = self.file_dialog()
+            .add_filter("RustF 项目", &["json"])
+            .set_file_name(DEFAULT_MANIFEST_NAME)
+            .set_title("选择新项目文件的保存位置")
+            .save_file();
+
+        if let Some(path) = handle {
+            self.remember_directory(&path);
+            let mut project = Project::new(path);
+            project.manifest.reference_doc = self.reference_doc_path.as_ref().map(|p| project.relativize(p));
+            match project.save() {
+                Ok(()) => {
+                    self.project = Some(project);
+                    self.project_panel_open = true;
+                }
+                Err(error_message) => self.open_info_dialog("创建项目失败", &error_message),
+            }
+        }
+    }
+
+    pub fn open_project(&mut self) {
+        let handle = self.file_dialog()
+            .add_filter("RustF 项目", &["json"])
+            .pick_file();
+
+        if let Some(path) = handle {
+            self.remember_directory(&path);
+            self.open_project_from_path(path);
+        }
+    }
+
+    pub fn open_project_from_path(&mut self, path: PathBuf) {
+        match Project::load(path) {
+            Ok(project) => {
+                if let Some(reference_doc) = &project.manifest.reference_doc {
+                    let reference_doc = project.resolve(reference_doc);
+                    if let Err(error_message) = self.load_reference_doc(reference_doc) {
+                        self.open_info_dialog("模板加载失败", &error_message);
+                    }
+                }
+                self.project = Some(project);
+                self.project_panel_open = true;
+            }
+            Err(error_message) => self.open_info_dialog("打开项目失败", &error_message),
+        }
+    }
+
+    pub fn save_project(&mut self) {
+        if let Some(project) = &mut self.project
+            && project.is_dirty
+            && let Err(error_message) = project.save() {
+            self.open_info_dialog("保存项目失败", &error_message);
+        }
+    }
+
+    /// 程序定期保存状态时写回项目清单。写入失败时只在第一次提示，
+    /// 之后同样的错误不再反复弹出，直到保存成功或错误发生变化。
+    pub fn autosave_project(&mut self) {
+        let Some(project) = &mut self.project else {
+            return;
+        };
+        if !project.is_dirty {
+            return;
+        }
+        let previous_error = project.save_error.clone();
+        if let Err(error_message) = project.save()
+            && previous_error.as_ref() != Some(&error_message) {
+            self.open_info_dialog("保存项目失败", &error_message);
+        }
+    }
+
+    pub fn close_project(&mut self) {
+        self.save_project();
+        self.project = None;
+        self.project_panel_open = false;
+    }
+
+    pub fn add_project_chapters(&mut self) {
+        if self.project.is_none() {
+            return;
+        }
+        let files = self.file_dialog()
+            .add_filter("Markdown", &["md", "markdown"])
+            .add_filter("Text", &["txt"])
+            .pick_files();
+
+        if let Some(paths) = files
+            && let Some(project) = &mut self.project {
+            for path in &paths {
+                project.add_chapter(path);
+            }
+        }
+    }
+
+    /// 将当前使用的导出模板记录到项目中
+    pub fn set_project_reference_doc_from_current(&mut self) {
+        if let Some(project) = &mut self.project {
+            project.manifest.reference_doc = self.reference_doc_path.as_ref().map(|p| project.relativize(p));
+            project.is_dirty = true;
+        }
+    }
+
+    pub fn set_project_variables_file(&mut self) {
+        if self.project.is_none() {
+            return;
+        }
+        let handle = self.file_dialog()
+            .add_filter("变量文件", &variables::all_extensions())
+            .pick_file();
+
+        if let Some(path) = handle
+            && let Some(project) = &mut self.project {
+            project.manifest.variables_file = Some(project.relativize(&path));
+            project.is_dirty = true;
+        }
+    }
+
+    /// 按清单顺序拼接所有章节。已在标签页中打开的章节使用编辑器中的内容（包括未保存的修改），
+    /// 其余章节从磁盘读取。章节源文件本身不会被修改。
+    pub fn assemble_project(&self) -> Result<String, String> {
+        let project = self.project.as_ref().ok_or_else(|| "当前没有打开的项目。".to_string())?;
+        let chapter_paths = project.chapter_paths();
+        if chapter_paths.is_empty() {
+            return Err("项目中还没有任何章节。".to_string());
+        }
+
+        let mut chapters = Vec::new();
+        let mut include_errors = Vec::new();
+        for path in &chapter_paths {
+            let open_document = self.documents.iter().find(|d| d.file_path.as_ref() == Some(path));
+            let content = match open_document {
+                // 保留占位符时，已打开的章节使用“模板赋值”中的值
+                Some(doc) if self.keep_template_markers => app::substitute_markers(&doc.markdown_text, &doc.marker_values),
+                Some(doc) => doc.markdown_text.clone(),
+                None => std::fs::read_to_string(path)
+                    .map_err(|e| format!("无法读取章节 {}：{}", path.display(), e))?,
+            };
+            // 每个章节开头都可以有自己的元数据，语法错误时 pandoc 会直接失败
+            metadata::validate(&content)
+                .map_err(|e| format!("章节 {} 的 YAML 元数据有误：{}", path.display(), e))?;
+            let expanded = includes::expand_includes(&content, Some(path));
+            include_errors.extend(expanded.errors.iter().map(|e| e.to_string()));
+            chapters.push(expanded.text);
+        }
+        if !include_errors.is_empty() {
+            return Err(format!("以下包含指令无法展开：\n\n{}", include_errors.join("\n")));
+        }
+        let mut combined_content = chapters.join("\n\n");
+
+        if let Some(variables_file) = &project.manifest.variables_file {
+            let assignments = variables::load_variables_file(&project.resolve(variables_file))?;
+            combined_content = app::substitute_markers(&combined_content, assignments.iter().map(|(marker, value)| (marker, value)));
+        }
+
+        Ok(combined_content)
+    }
+
+    /// 拼接项目的所有章节，按当前导出方案的格式导出。
+    /// `choose_output` 为 true 或还没有记住导出位置时，先询问导出到哪里。
+    pub fn export_project(&mut self, choose_output: bool) {
+        let markdown_content = match self.assemble_project() {
+            Ok(content) => content,
+            Err(error_message) => {
+                self.open_info_dialog("导出项目失败", &error_message);
+                return;
+            }
+        };
+        let Some(project) = &mut self.project else {
+            return;
+        };
+
+        // 格式和其他选项来自当前导出方案；项目自己的模板优先
+        let mut profile = self.export_settings.active().clone();
+        let format = profile.format;
+        if let Some(reference_doc) = &project.manifest.reference_doc {
+            profile.reference_doc = Some(project.resolve(reference_doc));
+        }
+
+        let remembered = project.manifest.export.output_path
+            .as_ref()
+            .filter(|_| !choose_output)
+            // 记住的导出位置沿用文件名，扩展名随导出格式变化
+            .map(|path| project.resolve(path).with_extension(format.extension()));
+        let output_path = match remembered {
+            Some(path) => path,
+            None => {
+                let current = project.manifest.export.output_path.as_ref().map(|path| project.resolve(path));
+                let directory = current.as_deref()
+                    .and_then(Path::parent)
+                    .unwrap_or(project.base_dir())
+                    .to_path_buf();
+                let default_file_name = match current.as_deref().and_then(Path::file_stem) {
+                    Some(stem) => format!("{}.{}", stem.to_string_lossy(), format.extension()),
+                    None => format!("{}.{}", project.manifest.name, format.extension()),
+                };
+                let Some(path) = rfd::FileDialog::new()
+                    .add_filter(format.label(), &[format.extension()])
+                    .set_directory(directory)
+                    .set_file_name(default_file_name)
+                    .save_file() else {
+                    return;
+                };
+                // 记住导出位置，下次导出项目时不再询问
+                project.manifest.export.output_path = Some(project.relativize(&path));
+                project.is_dirty = true;
+                path
+            }
+        };
+
+        let mut resource_dirs = vec![project.base_dir().to_path_buf()];
+        for path in project.chapter_paths() {
+            if let Some(dir) = path.parent()
+                && !resource_dirs.iter().any(|d| d == dir) {
+                resource_dirs.push(dir.to_path_buf());
+            }
+        }
+
+        self.queue_export(markdown_content, output_path, &profile, resource_dirs, None);
+    }
+}
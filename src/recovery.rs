@@ -0,0 +1,212 @@
+use crate::document::Document;
+use crate::state::MyApp;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// 自动保存的时间间隔
+pub const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+const SNAPSHOT_FILE_NAME: &str = "snapshot.json";
+const SESSION_LOCK_FILE_NAME: &str = "session.lock";
+
+/// 快照中的单个文档
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentSnapshot {
+    pub markdown_text: String,
+    pub file_path: Option<PathBuf>,
+    pub marker_values: HashMap<String, String>,
+    pub is_dirty: bool,
+}
+
+/// 自动保存时写入恢复目录的编辑器快照，包含所有打开的标签页
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecoverySnapshot {
+    pub documents: Vec<DocumentSnapshot>,
+    pub active_document: usize,
+    pub reference_doc_path: Option<PathBuf>,
+    /// 快照写入时间（Unix 时间戳，秒）
+    pub saved_at: u64,
+}
+
+impl RecoverySnapshot {
+    pub fn new(
+        documents: Vec<DocumentSnapshot>,
+        active_document: usize,
+        reference_doc_path: Option<PathBuf>,
+    ) -> Self {
+        let saved_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Self { documents, active_document, reference_doc_path, saved_at }
+    }
+
+    /// 快照中有未保存修改的文档数量
+    pub fn dirty_count(&self) -> usize {
+        self.documents.iter().filter(|d| d.is_dirty).count()
+    }
+
+    /// 距离快照写入已经过去的大致时间描述，用于恢复提示
+    pub fn age_description(&self) -> String {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let elapsed = now.saturating_sub(self.saved_at);
+        if elapsed < 60 {
+            "不到 1 分钟前".to_string()
+        } else if elapsed < 3600 {
+            format!("{} 分钟前", elapsed / 60)
+        } else if elapsed < 86400 {
+            format!("{} 小时前", elapsed / 3600)
+        } else {
+            format!("{} 天前", elapsed / 86400)
+        }
+    }
+}
+
+/// 恢复文件所在目录，位于 eframe 的应用数据目录下
+pub fn recovery_dir() -> Option<PathBuf> {
+    eframe::storage_dir(crate::APP_NAME).map(|dir| dir.join("recovery"))
+}
+
+/// 标记一次新的会话开始。
+/// 如果上一次会话没有正常结束（锁文件仍然存在），返回 true。
+pub fn begin_session() -> bool {
+    let Some(dir) = recovery_dir() else {
+        return false;
+    };
+    let lock_path = dir.join(SESSION_LOCK_FILE_NAME);
+    let unclean = lock_path.exists();
+
+    if std::fs::create_dir_all(&dir).is_ok() {
+        let _ = std::fs::write(&lock_path, std::process::id().to_string());
+    }
+    unclean
+}
+
+/// 正常退出时调用：删除锁文件和快照
+pub fn end_session() {
+    if let Some(dir) = recovery_dir() {
+        let _ = std::fs::remove_file(dir.join(SESSION_LOCK_FILE_NAME));
+    }
+    clear_snapshot();
+}
+
+pub fn write_snapshot(snapshot: &RecoverySnapshot) -> Result<(), String> {
+    let dir = recovery_dir().ok_or_else(|| "无法确定恢复文件目录。".to_string())?;
+    std::fs::create_dir_all(&dir).map_err(|e| format!("无法创建恢复目录: {}", e))?;
+
+    let json = serde_json::to_string(snapshot).map_err(|e| format!("无法序列化快照: {}", e))?;
+
+    // 先写入临时文件再重命名，避免写到一半时崩溃留下损坏的快照
+    let temp_path = dir.join(format!("{}.tmp", SNAPSHOT_FILE_NAME));
+    std::fs::write(&temp_path, json).map_err(|e| format!("无法写入快照: {}", e))?;
+    std::fs::rename(&temp_path, dir.join(SNAPSHOT_FILE_NAME))
+        .map_err(|e| format!("无法写入快照: {}", e))
+}
+
+pub fn load_snapshot() -> Option<RecoverySnapshot> {
+    let path = recovery_dir()?.join(SNAPSHOT_FILE_NAME);
+    let json = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+pub fn clear_snapshot() {
+    if let Some(dir) = recovery_dir() {
+        let _ = std::fs::remove_file(dir.join(SNAPSHOT_FILE_NAME));
+    }
+}
+
+impl MyApp {
+    /// 在有文档存在未保存的修改且距上次自动保存超过间隔时，写入恢复快照
+    pub fn autosave_if_needed(&mut self, ctx: &egui::Context) {
+        if !self.documents.iter().any(|d| d.is_dirty) {
+            // 修改已保存，旧快照不再需要
+            if self.autosaved_hash.take().is_some() {
+                clear_snapshot();
+            }
+            return;
+        }
+
+        // 即使用户停止操作也要按时触发下一次检查
+        ctx.request_repaint_after(AUTOSAVE_INTERVAL);
+
+        if self.last_autosave.elapsed() < AUTOSAVE_INTERVAL {
+            return;
+        }
+        self.last_autosave = std::time::Instant::now();
+
+        let hash = self.documents_hash();
+        if self.autosaved_hash == Some(hash) {
+            return;
+        }
+
+        let documents = self.documents
+            .iter()
+            .map(|doc| DocumentSnapshot {
+                markdown_text: doc.markdown_text.clone(),
+                file_path: doc.file_path.clone(),
+                marker_values: doc.marker_values.clone(),
+                is_dirty: doc.is_dirty,
+            })
+            .collect();
+        let snapshot = RecoverySnapshot::new(
+            documents,
+            self.active_document,
+            self.reference_doc_path.clone(),
+        );
+        // 自动保存失败不打断编辑，下一个间隔会再次尝试
+        if write_snapshot(&snapshot).is_ok() {
+            self.autosaved_hash = Some(hash);
+        }
+    }
+
+    /// 所有文档的内容和修改状态的哈希，内容没有变化时不重复写入快照
+    fn documents_hash(&self) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        for doc in &self.documents {
+            doc.markdown_text.hash(&mut hasher);
+            doc.is_dirty.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    /// 用启动时发现的快照替换当前打开的文档
+    pub fn restore_recovery_snapshot(&mut self) {
+        let Some(snapshot) = self.recovery_snapshot.take() else {
+            return;
+        };
+        if snapshot.documents.is_empty() {
+            return;
+        }
+
+        self.documents.clear();
+        for saved in snapshot.documents {
+            let mut doc = Document::new(self.next_document_id, saved.markdown_text, saved.file_path);
+            self.next_document_id += 1;
+            doc.marker_values = saved.marker_values;
+            // 快照中被修改过的内容尚未写回原文件
+            doc.is_dirty = saved.is_dirty;
+            self.documents.push(doc);
+        }
+        self.active_document = snapshot.active_document.min(self.documents.len() - 1);
+        // 磁盘上的快照与恢复的内容一致，保留它直到内容再次变化或被保存，
+        // 以免恢复后、下一次自动保存前再次崩溃时丢失内容
+        self.autosaved_hash = Some(self.documents_hash());
+
+        if let Some(path) = snapshot.reference_doc_path
+            && let Err(error_message) = self.load_reference_doc(path) {
+            self.open_info_dialog("模板加载失败", &error_message);
+        }
+    }
+
+    /// 放弃快照并删除恢复文件
+    pub fn discard_recovery_snapshot(&mut self) {
+        self.recovery_snapshot = None;
+        clear_snapshot();
+    }
+}
@@ -0,0 +1,161 @@
+use crate::export::ExportSettings;
+use crate::import::ImportOptions;
+use crate::state::MyApp;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// 在 eframe 存储中保存设置使用的键
+pub const SETTINGS_KEY: &str = "app_settings";
+
+/// 最多记住的最近使用文件夹数量
+const MAX_RECENT_DIRECTORIES: usize = 8;
+/// “最近打开”和“最近模板”菜单中最多显示的条目数量
+const MAX_RECENT_FILES: usize = 10;
+
+/// 需要跨重启保留的应用设置与会话信息。
+/// 窗口位置和大小由 eframe 自身负责保存。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppSettings {
+    pub scroll_linked: bool,
+    /// 模板赋值时是否保留占位符，只在预览和导出时替换
+    pub keep_template_markers: bool,
+    pub reference_doc_path: Option<PathBuf>,
+    /// 最近在文件对话框中使用过的文件夹，最新的在最前面
+    pub recent_directories: Vec<PathBuf>,
+    /// 退出时激活的文档
+    pub last_opened_file: Option<PathBuf>,
+    /// 退出时在标签页中打开的、已保存到磁盘的文档
+    pub open_files: Vec<PathBuf>,
+    /// 退出时打开的项目清单
+    pub last_project: Option<PathBuf>,
+    /// 最近打开或合并过的文档，最新的在最前面
+    pub recent_files: Vec<PathBuf>,
+    /// 最近使用过的 DOCX 模板，最新的在最前面
+    pub recent_templates: Vec<PathBuf>,
+    /// 用户指定的 Pandoc 程序路径，为空时自动查找
+    pub pandoc_path: Option<PathBuf>,
+    pub export: ExportSettings,
+    pub import: ImportOptions,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        Self {
+            scroll_linked: true,
+            keep_template_markers: false,
+            reference_doc_path: None,
+            recent_directories: Vec::new(),
+            last_opened_file: None,
+            open_files: Vec::new(),
+            last_project: None,
+            recent_files: Vec::new(),
+            recent_templates: Vec::new(),
+            pandoc_path: None,
+            export: ExportSettings::default(),
+            import: ImportOptions::default(),
+        }
+    }
+}
+
+impl MyApp {
+    /// 从当前状态收集需要持久化的设置
+    pub fn collect_settings(&self) -> AppSettings {
+        AppSettings {
+            scroll_linked: self.scroll_linked,
+            keep_template_markers: self.keep_template_markers,
+            reference_doc_path: self.reference_doc_path.clone(),
+            recent_directories: self.recent_directories.clone(),
+            last_opened_file: self.doc().file_path.clone(),
+            open_files: self.documents.iter().filter_map(|d| d.file_path.clone()).collect(),
+            last_project: self.project.as_ref().map(|p| p.manifest_path.clone()),
+            recent_files: self.recent_files.clone(),
+            recent_templates: self.recent_templates.clone(),
+            pandoc_path: self.custom_pandoc_path.clone(),
+            export: self.export_settings.clone(),
+            import: self.import_options.clone(),
+        }
+    }
+
+    /// 启动时应用上次保存的设置：重新解析参考文档，并重新打开上次的文档标签页
+    pub fn apply_settings(&mut self, settings: AppSettings, restore_last_file: bool) {
+        self.scroll_linked = settings.scroll_linked;
+        self.keep_template_markers = settings.keep_template_markers;
+        self.export_settings = settings.export;
+        self.export_settings.normalize();
+        self.import_options = settings.import;
+
+        if let Some(path) = settings.reference_doc_path
+            && let Err(error_message) = self.load_reference_doc(path.clone()) {
+            self.open_info_dialog(
+                "模板加载失败",
+                &format!("无法重新加载上次使用的模板 {}：\n{}", path.display(), error_message),
+            );
+        }
+
+        // 项目中的模板会覆盖上面恢复的全局模板
+        if let Some(path) = settings.last_project
+            && path.is_file() {
+            self.open_project_from_path(path);
+        }
+
+        if restore_last_file {
+            for path in settings.open_files.into_iter().filter(|p| p.is_file()) {
+                self.open_file_from_path(path);
+            }
+            // 最后切换回退出时激活的标签页（旧版本设置中只有这一项）
+            if let Some(path) = settings.last_opened_file
+                && path.is_file() {
+                self.open_file_from_path(path);
+            }
+        }
+
+        // 重新打开文件和模板时不应改变最近列表的顺序，因此最后再恢复这些列表
+        self.recent_directories = settings.recent_directories;
+        self.recent_directories.retain(|dir| dir.is_dir());
+        self.recent_files = settings.recent_files;
+        self.recent_templates = settings.recent_templates;
+        self.prune_recent_lists();
+    }
+
+    /// 创建一个默认定位到最近使用文件夹的文件对话框
+    pub fn file_dialog(&self) -> rfd::FileDialog {
+        let dialog = rfd::FileDialog::new();
+        match self.recent_directories.first() {
+            Some(dir) => dialog.set_directory(dir),
+            None => dialog,
+        }
+    }
+
+    /// 记录文件所在的文件夹，供下次打开文件对话框时使用
+    pub fn remember_directory(&mut self, file_path: &Path) {
+        let Some(dir) = file_path.parent() else {
+            return;
+        };
+        if dir.as_os_str().is_empty() {
+            return;
+        }
+        push_recent(&mut self.recent_directories, dir, MAX_RECENT_DIRECTORIES);
+    }
+
+    pub fn remember_recent_file(&mut self, path: &Path) {
+        push_recent(&mut self.recent_files, path, MAX_RECENT_FILES);
+    }
+
+    pub fn remember_recent_template(&mut self, path: &Path) {
+        push_recent(&mut self.recent_templates, path, MAX_RECENT_FILES);
+    }
+
+    /// 移除已经不存在的最近文件和模板
+    pub fn prune_recent_lists(&mut self) {
+        self.recent_files.retain(|path| path.is_file());
+        self.recent_templates.retain(|path| path.is_file());
+    }
+}
+
+/// 将路径移动到列表最前面，并限制列表长度
+fn push_recent(list: &mut Vec<PathBuf>, path: &Path, max_len: usize) {
+    list.retain(|p| p != path);
+    list.insert(0, path.to_path_buf());
+    list.truncate(max_len);
+}
@@ -1,20 +1,69 @@
-use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use crate::batch::BatchExport;
+use crate::document::Document;
+use crate::file_handler::{MergeEntry, MergeSeparator, SplitSegment};
+use crate::export::ExportSettings;
+use crate::import::{ImportOptions, PendingImport};
 use crate::font_utils;
+use crate::jobs::JobManager;
+use crate::metadata::MetadataEditor;
+use crate::pandoc_locator::PandocInfo;
+use crate::pandoc_log::LogEntry;
+use crate::project::Project;
+use crate::recovery::{self, RecoverySnapshot};
+use crate::settings::{self, AppSettings};
+use crate::variables::VariableFormat;
 use regex::Regex;
 
+/// 因文档存在未保存的修改而被暂缓执行的操作
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PendingAction {
+    /// 关闭指定编号的标签页
+    CloseDocument(u64),
+    /// 关闭程序窗口
+    Close,
+}
+
 pub struct MyApp {
-    pub markdown_text: String,
-    pub cache: egui_commonmark::CommonMarkCache,
+    /// 所有打开的文档，按标签页顺序排列，至少包含一个文档
+    pub documents: Vec<Document>,
+    /// 当前激活的标签页在 `documents` 中的索引
+    pub active_document: usize,
+    /// 下一个新建文档使用的编号
+    pub next_document_id: u64,
+    /// 最近一次设置到窗口上的标题，避免每帧重复发送视口命令
+    pub window_title: String,
     pub scroll_linked: bool,
-    pub scroll_proportion: f32,
-    pub preview_max_scroll: f32,
     
     pub assignment_window_open: bool,
-    pub template_markers: Vec<String>,
-    pub marker_values: HashMap<String, String>,
-    pub conversion_receiver: Option<crossbeam_channel::Receiver<Result<String, String>>>,
-    pub import_receiver: Option<crossbeam_channel::Receiver<Result<String, String>>>,
+    /// 模板赋值时保留文档中的 `{{变量}}`，只在预览和导出时替换为已赋的值
+    pub keep_template_markers: bool,
+    /// 导入、导出等 pandoc 任务队列
+    pub jobs: JobManager,
+    /// 最近结束的任务在状态栏中显示的结果
+    pub job_status_message: Option<String>,
+    /// 最近的 pandoc 调用记录，最新的在最后
+    pub pandoc_log: Vec<LogEntry>,
+    pub log_panel_open: bool,
+    pub metadata_panel_open: bool,
+    pub metadata_editor: Option<MetadataEditor>,
     pub reference_doc_path: Option<std::path::PathBuf>,
+    /// 用户在设置中指定的 Pandoc 程序路径，None 表示自动查找
+    pub custom_pandoc_path: Option<PathBuf>,
+    /// 当前使用的 Pandoc 程序及其版本
+    pub pandoc: PandocInfo,
+    /// 已确认可以运行的 PDF 引擎，每个引擎只检查一次，避免每次导出都在界面线程上启动它
+    pub available_pdf_engines: HashSet<String>,
+    pub export_settings: ExportSettings,
+    pub export_dialog_open: bool,
+    pub filter_manager_open: bool,
+    pub batch_export: BatchExport,
+    pub batch_export_dialog_open: bool,
+    pub import_options: ImportOptions,
+    pub import_options_dialog_open: bool,
+    /// 已选择文件、等待确认导入选项的导入
+    pub pending_import: Option<PendingImport>,
     pub about_window_open: bool,
     pub paragraph_styles: Vec<String>,
     pub character_styles: Vec<String>,
@@ -30,6 +79,10 @@ pub struct MyApp {
     pub palette_filtered_styles: Vec<(String, bool)>,
     /// 标志，指示是否需要滚动到选中的项目
     pub palette_should_scroll_to_selected: bool,
+    /// 插入引用的选择框
+    pub citation_picker_open: bool,
+    pub citation_search_text: String,
+    pub citation_selected_index: usize,
     // 正则表达式
     pub underline_regex: Regex,
 
@@ -41,26 +94,94 @@ pub struct MyApp {
     // 模板导入
     pub import_dialog_open: bool,
     pub import_text_area: String,
+    /// 粘贴的变量文本的格式
+    pub import_text_format: VariableFormat,
+
+    // 未保存修改提示
+    pub unsaved_dialog_open: bool,
+    pub pending_action: Option<PendingAction>,
+    /// 用户已确认放弃或保存修改，允许关闭窗口
+    pub allow_close: bool,
+
+    // 自动保存与崩溃恢复
+    pub last_autosave: std::time::Instant,
+    /// 最近一次写入快照时文本内容的哈希，用于跳过没有变化的自动保存
+    pub autosaved_hash: Option<u64>,
+    /// 启动时发现的上次异常退出留下的快照
+    pub recovery_snapshot: Option<RecoverySnapshot>,
+    pub recovery_dialog_open: bool,
+
+    /// 上次检查已打开文件是否被外部修改的时间
+    pub last_watch_check: std::time::Instant,
+
+    /// 最近在文件对话框中使用过的文件夹
+    pub recent_directories: Vec<PathBuf>,
+    pub recent_files: Vec<PathBuf>,
+    pub recent_templates: Vec<PathBuf>,
+
+    // 合并文件对话框
+    pub merge_dialog_open: bool,
+    pub merge_entries: Vec<MergeEntry>,
+    pub merge_separator: MergeSeparator,
+    /// 合并时是否插入来源标记，以便之后拆分回原文件
+    pub merge_insert_source_markers: bool,
+
+    // 拆分回原文件确认对话框
+    pub split_dialog_open: bool,
+    pub split_plan: Vec<SplitSegment>,
+
+    // 项目
+    pub project: Option<Project>,
+    pub project_panel_open: bool,
 }
 impl MyApp {
     pub fn new(cc: &eframe::CreationContext) -> Self {
         font_utils::setup_chinese_fonts(&cc.egui_ctx);
 
-        let underline_regex = Regex::new(r"\{\{.*?\}\}").unwrap();
+        let underline_regex = Regex::new(r"\{\{[^>].*?\}\}").unwrap();
+
+        // 上次会话没有正常结束时，尝试读取自动保存的快照
+        let recovery_snapshot = if recovery::begin_session() {
+            recovery::load_snapshot()
+        } else {
+            recovery::clear_snapshot();
+            None
+        };
+
+        let settings: AppSettings = cc.storage
+            .and_then(|storage| eframe::get_value(storage, settings::SETTINGS_KEY))
+            .unwrap_or_default();
+
+        // 启动时检测一次 Pandoc，以便尽早提示缺失或版本过旧
+        let pandoc = PandocInfo::detect(settings.pandoc_path.as_deref());
 
-        Self {
-            markdown_text: include_str!("../user_guide.md").to_owned(),
-            cache: egui_commonmark::CommonMarkCache::default(),
+        let mut app = Self {
+            documents: vec![Document::new(0, include_str!("../user_guide.md").to_owned(), None)],
+            active_document: 0,
+            next_document_id: 1,
+            window_title: String::new(),
             scroll_linked: true,
-            scroll_proportion: 0.0,
-            preview_max_scroll: 0.0,
             
             assignment_window_open: false,
-            template_markers: Vec::new(),
-            marker_values: HashMap::new(),
-            conversion_receiver: None,
-            import_receiver: None,
+            keep_template_markers: false,
+            jobs: JobManager::default(),
+            job_status_message: None,
+            pandoc_log: Vec::new(),
+            log_panel_open: false,
+            metadata_panel_open: false,
+            metadata_editor: None,
             reference_doc_path: None,
+            custom_pandoc_path: settings.pandoc_path.clone(),
+            pandoc,
+            available_pdf_engines: HashSet::new(),
+            export_settings: ExportSettings::default(),
+            export_dialog_open: false,
+            filter_manager_open: false,
+            batch_export: BatchExport::default(),
+            batch_export_dialog_open: false,
+            import_options: ImportOptions::default(),
+            import_options_dialog_open: false,
+            pending_import: None,
             about_window_open: false,
             paragraph_styles: Vec::new(),
             character_styles: Vec::new(),
@@ -69,13 +190,111 @@ impl MyApp {
             palette_selected_index: 0,
             palette_filtered_styles: Vec::new(),
             palette_should_scroll_to_selected: false,
+            citation_picker_open: false,
+            citation_search_text: String::new(),
+            citation_selected_index: 0,
             underline_regex,
             info_dialog_open: false,
             info_dialog_title: String::new(),
             info_dialog_message: String::new(),
             import_dialog_open: false,
             import_text_area: String::new(),
+            import_text_format: VariableFormat::default(),
+            unsaved_dialog_open: false,
+            pending_action: None,
+            allow_close: false,
+            last_autosave: std::time::Instant::now(),
+            autosaved_hash: None,
+            recovery_dialog_open: recovery_snapshot.is_some(),
+            last_watch_check: std::time::Instant::now(),
+            recovery_snapshot,
+            recent_directories: Vec::new(),
+            recent_files: Vec::new(),
+            recent_templates: Vec::new(),
+            merge_dialog_open: false,
+            merge_entries: Vec::new(),
+            merge_separator: MergeSeparator::BlankLine,
+            merge_insert_source_markers: true,
+            split_dialog_open: false,
+            split_plan: Vec::new(),
+            project: None,
+            project_panel_open: false,
+        };
+
+        // 有待恢复的快照时，由用户决定是否恢复，不再自动打开上次的文档
+        let restore_last_file = app.recovery_snapshot.is_none();
+        app.apply_settings(settings, restore_last_file);
+        if let Some(warning) = app.pandoc.warning() {
+            app.open_info_dialog("Pandoc 检测", &warning);
+        }
+        app
+    }
+
+    /// 当前激活的文档
+    pub fn doc(&self) -> &Document {
+        &self.documents[self.active_document]
+    }
+
+    pub fn doc_mut(&mut self) -> &mut Document {
+        &mut self.documents[self.active_document]
+    }
+
+    /// 打开一个新的文档标签页并激活它。
+    /// 如果当前标签页是未修改的未命名文档（例如启动时的使用指南），则直接替换它。
+    pub fn add_document(&mut self, markdown_text: String, file_path: Option<PathBuf>) -> &mut Document {
+        let document = Document::new(self.next_document_id, markdown_text, file_path);
+        self.next_document_id += 1;
+
+        if self.doc().is_replaceable() {
+            let index = self.active_document;
+            self.documents[index] = document;
+        } else {
+            self.documents.push(document);
+            self.active_document = self.documents.len() - 1;
+        }
+        self.doc_mut()
+    }
+
+    /// 新建一个空白文档
+    pub fn new_document(&mut self) {
+        let document = Document::new(self.next_document_id, String::new(), None);
+        self.next_document_id += 1;
+        self.documents.push(document);
+        self.active_document = self.documents.len() - 1;
+    }
+
+    pub fn document_index(&self, id: u64) -> Option<usize> {
+        self.documents.iter().position(|d| d.id == id)
+    }
+
+    /// 直接关闭指定编号的标签页，不检查未保存的修改
+    pub fn close_document(&mut self, id: u64) {
+        let Some(index) = self.document_index(id) else {
+            return;
+        };
+        self.documents.remove(index);
+
+        if self.documents.is_empty() {
+            self.new_document();
+        } else if index < self.active_document || self.active_document >= self.documents.len() {
+            self.active_document = self.active_document.saturating_sub(1);
+        }
+    }
+
+    /// 按偏移量切换标签页，循环到首尾
+    pub fn cycle_document(&mut self, offset: isize) {
+        let len = self.documents.len() as isize;
+        self.active_document = (self.active_document as isize + offset).rem_euclid(len) as usize;
+    }
+
+    /// 将当前标签页向左（负数）或向右（正数）移动一位
+    pub fn move_active_document(&mut self, offset: isize) {
+        let target = self.active_document as isize + offset;
+        if target < 0 || target >= self.documents.len() as isize {
+            return;
         }
+        self.documents.swap(self.active_document, target as usize);
+        self.active_document = target as usize;
     }
 
     /// 处理拖入的文件或文件夹
@@ -120,8 +339,9 @@ impl MyApp {
     /// 将 Markdown 图片代码插入到编辑器中
     /// 它会找到当前光标位置并进行插入
     pub fn insert_image_markdown(&mut self, ctx: &egui::Context, file_path: &std::path::Path) {
-        // `main_editor_id` 必须与 `panels.rs` 中 TextEdit 的 id_source 相同
-        let editor_id = egui::Id::new("main_editor_id");
+        // 必须与 `panels.rs` 中当前标签页 TextEdit 的 id 相同
+        let editor_id = self.doc().editor_id();
+        let doc = self.doc_mut();
         
         // 尝试加载编辑器的状态以获取光标位置
         if let Some(mut state) = egui::TextEdit::load_state(ctx, editor_id) {
@@ -133,13 +353,13 @@ impl MyApp {
             let markdown_image = format!("![{}]({})\n", filename, file_path.to_string_lossy());
             
             // 获取光标位置，如果没有光标则插入到文本末尾
-            let current_pos = state.cursor.char_range().map(|r| r.primary.index).unwrap_or(self.markdown_text.chars().count());
+            let current_pos = state.cursor.char_range().map(|r| r.primary.index).unwrap_or(doc.markdown_text.chars().count());
             
             // 将字符串切片并插入新文本
-            let text = self.markdown_text.clone();
+            let text = doc.markdown_text.clone();
             let chars = text.chars().collect::<Vec<_>>();
             let (prefix, suffix) = chars.split_at(current_pos);
-            self.markdown_text = prefix.iter().collect::<String>() + &markdown_image + &suffix.iter().collect::<String>();
+            doc.markdown_text = prefix.iter().collect::<String>() + &markdown_image + &suffix.iter().collect::<String>();
             
             // 将光标移动到新插入文本之后（包括换行符），以便连续拖入多张图片时能正确插入
             let new_cursor_pos = current_pos + markdown_image.chars().count();
@@ -147,12 +367,35 @@ impl MyApp {
             let new_range = egui::text::CCursorRange::one(new_cursor);
             state.cursor.set_char_range(Some(new_range));
             egui::TextEdit::store_state(ctx, editor_id, state);
+            doc.is_dirty = true;
         } else {
             // 如果无法获取编辑器状态（例如编辑器没有焦点），则直接在文档末尾添加
             let filename = file_path.file_name()
                 .map(|s| s.to_string_lossy())
                 .unwrap_or_else(|| "image".into());
-            self.markdown_text.push_str(&format!("\n\n![{}]({})\n", filename, file_path.to_string_lossy()));
+            doc.markdown_text.push_str(&format!("\n\n![{}]({})\n", filename, file_path.to_string_lossy()));
+            doc.is_dirty = true;
         }
     }
-}
\ No newline at end of file
+
+    /// 在光标所在行之前插入独占一行的目录标记，导出时在此处生成目录
+    pub fn insert_toc_marker(&mut self, ctx: &egui::Context) {
+        let editor_id = self.doc().editor_id();
+        let doc = self.doc_mut();
+        let char_index = egui::TextEdit::load_state(ctx, editor_id)
+            .and_then(|state| state.cursor.char_range())
+            .map(|range| range.primary.index)
+            .unwrap_or(0);
+        let byte_index = doc.markdown_text
+            .char_indices()
+            .nth(char_index)
+            .map(|(i, _)| i)
+            .unwrap_or(doc.markdown_text.len());
+        let line_start = doc.markdown_text[..byte_index].rfind('\n').map_or(0, |i| i + 1);
+
+        let marker = format!("{}\n\n", crate::export::TOC_MARKER);
+        doc.markdown_text.insert_str(line_start, &marker);
+        doc.is_dirty = true;
+        doc.pending_cursor = Some(doc.markdown_text[..line_start].chars().count() + marker.chars().count());
+    }
+}
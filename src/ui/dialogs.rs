@@ -1,6 +1,12 @@
 use eframe::egui;
+use std::path::PathBuf;
 
+use crate::citations::{BIBLIOGRAPHY_EXTENSIONS, CitationIndex};
+use crate::export::{self, ExportFormat, ExportProfile, FilterEntry, HIGHLIGHT_STYLES, KNOWN_PDF_ENGINES};
+use crate::file_handler::{MergeSeparator, SplitStatus};
+use crate::import::{self, MarkdownFlavor, TableStyle, TrackChanges, WrapMode};
 use crate::state::MyApp;
+use crate::variables::VariableFormat;
 
 impl MyApp {
     pub fn show_about_window(&mut self, ctx: &egui::Context) {
@@ -14,6 +20,8 @@ impl MyApp {
                     ui.heading("文档风格转换器");
                     
                     ui.label(format!("版本: {}", env!("CARGO_PKG_VERSION")));
+                    ui.label(self.pandoc.summary())
+                        .on_hover_text(self.pandoc.executable.display().to_string());
 
                     ui.add_space(10.0);
                     ui.label("作者: 冯思昕");
@@ -46,13 +54,16 @@ impl MyApp {
     pub fn show_assignment_window(&mut self, ctx: &egui::Context) {
         let mut apply_and_close = false;
         let mut cancel_and_close = false;
+        let active = self.active_document;
+        let doc = &mut self.documents[active];
+        let keep_template_markers = &mut self.keep_template_markers;
 
         egui::Window::new("模板变量赋值")
             .open(&mut self.assignment_window_open)
             .resizable(true)
             .default_width(400.0)
             .show(ctx, |ui| {
-                if self.template_markers.is_empty() {
+                if doc.template_markers.is_empty() {
                     ui.label("在文档中没有找到 {{...}} 格式的标记。");
                     return;
                 }
@@ -62,10 +73,11 @@ impl MyApp {
 
                 egui::ScrollArea::vertical().show(ui, |ui| {
                     egui::Grid::new("assignment_grid").num_columns(2).show(ui, |ui| {
-                        for marker in &self.template_markers {
+                        for marker in &doc.template_markers {
                             ui.label(marker);
-                            if let Some(value) = self.marker_values.get_mut(marker) {
-                                ui.text_edit_singleline(value);
+                            if let Some(value) = doc.marker_values.get_mut(marker) {
+                                // 值可以有多行，例如从 JSON 或 YAML 文件导入的地址
+                                ui.add(egui::TextEdit::multiline(value).desired_rows(1));
                             }
                             ui.end_row();
                         }
@@ -73,10 +85,13 @@ impl MyApp {
                 });
 
                 ui.add_space(10.0);
+                ui.checkbox(keep_template_markers, "保留占位符，只在预览和导出时替换")
+                    .on_hover_text("文档中的 {{...}} 保持不变，同一份模板可以用不同的值反复导出");
                 ui.separator();
                 
                 ui.horizontal(|ui| {
-                    if ui.button("全部替换").clicked() {
+                    let apply_label = if *keep_template_markers { "确定" } else { "全部替换" };
+                    if ui.button(apply_label).clicked() {
                         apply_and_close = true;
                     }
 
@@ -87,8 +102,11 @@ impl MyApp {
             });
 
         if apply_and_close {
-            self.apply_template_variables_to_markdown();
-            self.template_markers.clear();
+            // 保留占位符时，值只保存在文档的变量表中，预览会立即显示替换后的内容
+            if !self.keep_template_markers {
+                self.apply_template_variables_to_markdown();
+                self.doc_mut().template_markers.clear();
+            }
             self.assignment_window_open = false;
         }
         if cancel_and_close {
@@ -234,6 +252,114 @@ impl MyApp {
         }
     }
 
+    pub fn open_citation_picker(&mut self) {
+        self.citation_search_text.clear();
+        self.citation_selected_index = 0;
+        self.citation_picker_open = true;
+    }
+
+    /// 从参考文献中选择一条记录，在光标处插入 `[@key]`
+    pub fn show_citation_picker(&mut self, ctx: &egui::Context) {
+        let mut key_to_insert = None;
+
+        let profile_bibliography = self.export_settings.active().bibliography.clone();
+        let doc = &mut self.documents[self.active_document];
+        let base_dir = doc.base_dir().map(|dir| dir.to_path_buf());
+        let search_text = self.citation_search_text.to_lowercase();
+        let (matches, errors) = match CitationIndex::refresh(
+            &mut doc.citation_index,
+            &doc.markdown_text,
+            base_dir.as_deref(),
+            profile_bibliography.as_deref(),
+        ) {
+            Some(index) => {
+                let matches: Vec<(String, String)> = index.entries.iter()
+                    .filter(|entry| {
+                        entry.key.to_lowercase().contains(&search_text)
+                            || entry.title.to_lowercase().contains(&search_text)
+                    })
+                    .map(|entry| (entry.key.clone(), entry.title.clone()))
+                    .collect();
+                (Some(matches), index.errors.clone())
+            }
+            None => (None, Vec::new()),
+        };
+        let mut scroll_to_selected = false;
+
+        let area = egui::Area::new("citation_picker_area".into())
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO);
+
+        let response = area.show(ctx, |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.set_max_width(400.0);
+
+                let Some(matches) = &matches else {
+                    ui.label("当前文档没有可用的参考文献。");
+                    ui.label("请在文档属性中添加 bibliography 字段，");
+                    ui.label("或在“导出...”对话框的导出方案中选择参考文献文件。");
+                    return;
+                };
+                for error in &errors {
+                    ui.colored_label(ui.visuals().warn_fg_color, format!("⚠ {}", error));
+                }
+
+                let search_box_id = ui.id().with("citation_search");
+                let search_box = ui.add(
+                    egui::TextEdit::singleline(&mut self.citation_search_text)
+                        .hint_text("搜索引用键或标题...")
+                        .id(search_box_id),
+                );
+                ctx.memory_mut(|m| m.request_focus(search_box_id));
+                if search_box.changed() {
+                    self.citation_selected_index = 0;
+                }
+                self.citation_selected_index = self.citation_selected_index.min(matches.len().saturating_sub(1));
+
+                if !matches.is_empty() {
+                    if ui.input(|i| i.key_pressed(egui::Key::ArrowDown)) {
+                        self.citation_selected_index = (self.citation_selected_index + 1) % matches.len();
+                        scroll_to_selected = true;
+                    }
+                    if ui.input(|i| i.key_pressed(egui::Key::ArrowUp)) {
+                        self.citation_selected_index = (self.citation_selected_index + matches.len() - 1) % matches.len();
+                        scroll_to_selected = true;
+                    }
+                    if ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                        key_to_insert = Some(matches[self.citation_selected_index].0.clone());
+                    }
+                }
+
+                ui.separator();
+
+                egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    ui.set_width(ui.available_width());
+                    if matches.is_empty() {
+                        ui.weak("没有匹配的记录。");
+                    }
+                    for (i, (key, title)) in matches.iter().enumerate() {
+                        let label = if title.is_empty() { format!("@{}", key) } else { format!("@{}  {}", key, title) };
+                        let response = ui.selectable_label(self.citation_selected_index == i, label);
+                        if response.clicked() {
+                            key_to_insert = Some(key.clone());
+                        }
+                        if scroll_to_selected && self.citation_selected_index == i {
+                            response.scroll_to_me(Some(egui::Align::Center));
+                        }
+                    }
+                });
+            });
+        });
+
+        if let Some(key) = key_to_insert {
+            self.insert_at_cursor(ctx, &format!("[@{}]", key));
+            self.citation_picker_open = false;
+        }
+
+        if response.response.clicked_elsewhere() || ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+            self.citation_picker_open = false;
+        }
+    }
+
     pub fn show_info_dialog(&mut self, ctx: &egui::Context) {
         let mut close_button_clicked = false;
         egui::Window::new(self.info_dialog_title.as_str())
@@ -254,8 +380,629 @@ impl MyApp {
         }
     }
 
+    /// 选择导出方案并编辑方案中的格式和 pandoc 选项
+    pub fn show_export_dialog(&mut self, ctx: &egui::Context) {
+        let mut export_clicked = false;
+        let mut cancel_clicked = false;
+        let mut pick_reference = false;
+        let mut pick_css = false;
+        let mut add_filter = false;
+        let mut pick_bibliography = false;
+        let mut open_filter_manager = false;
+        let mut pick_csl = false;
+
+        let docx_template = self.reference_doc_path
+            .as_ref()
+            .map(|p| p.display().to_string());
+        let settings = &mut self.export_settings;
+        let filter_library = settings.filter_library.clone();
+
+        egui::Window::new("导出")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .open(&mut self.export_dialog_open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("导出方案：");
+                    egui::ComboBox::from_id_salt("export_profile_combo")
+                        .selected_text(settings.active().name.clone())
+                        .show_ui(ui, |ui| {
+                            for (index, profile) in settings.profiles.iter().enumerate() {
+                                ui.selectable_value(&mut settings.active_profile, index, &profile.name);
+                            }
+                        });
+                    if ui.button("新建").clicked() {
+                        let name = settings.unique_name("新方案");
+                        settings.profiles.push(ExportProfile { name, ..Default::default() });
+                        settings.active_profile = settings.profiles.len() - 1;
+                    }
+                    if ui.button("复制").clicked() {
+                        let mut copy = settings.active().clone();
+                        copy.name = settings.unique_name(&format!("{} 副本", copy.name));
+                        settings.profiles.push(copy);
+                        settings.active_profile = settings.profiles.len() - 1;
+                    }
+                    if ui.add_enabled(settings.profiles.len() > 1, egui::Button::new("删除")).clicked() {
+                        settings.profiles.remove(settings.active_profile);
+                        settings.normalize();
+                    }
+                });
+                let profile = settings.active_mut();
+                ui.horizontal(|ui| {
+                    ui.label("方案名称：");
+                    ui.text_edit_singleline(&mut profile.name);
+                });
+                ui.separator();
+
+                ui.label("导出格式：");
+                for format in ExportFormat::ALL {
+                    ui.radio_value(&mut profile.format, format, format.label());
+                }
+                ui.separator();
+
+                match profile.format {
+                    ExportFormat::Docx | ExportFormat::Odt => {
+                        ui.horizontal(|ui| {
+                            let reference = match (&profile.reference_doc, profile.format) {
+                                (Some(path), _) => path.display().to_string(),
+                                (None, ExportFormat::Docx) => match &docx_template {
+                                    Some(template) => format!("（使用当前导出模板 {}）", template),
+                                    None => "（未设置）".to_string(),
+                                },
+                                (None, _) => "（未设置）".to_string(),
+                            };
+                            ui.label(format!("参考文档：{}", reference));
+                            if ui.button("选择...").clicked() {
+                                pick_reference = true;
+                            }
+                            if profile.reference_doc.is_some() && ui.button("清除").clicked() {
+                                profile.reference_doc = None;
+                            }
+                        });
+                    }
+                    ExportFormat::Html => {
+                        ui.horizontal(|ui| {
+                            let css = profile.html_css
+                                .as_ref()
+                                .map(|p| p.display().to_string())
+                                .unwrap_or_else(|| "（未设置）".to_string());
+                            ui.label(format!("CSS 样式表：{}", css));
+                            if ui.button("选择...").clicked() {
+                                pick_css = true;
+                            }
+                            if profile.html_css.is_some() && ui.button("清除").clicked() {
+                                profile.html_css = None;
+                            }
+                        });
+                        ui.checkbox(&mut profile.html_embed_resources, "生成单个文件（嵌入图片和样式）");
+                    }
+                    ExportFormat::Epub => {
+                        ui.label("EPUB 没有额外的选项。");
+                    }
+                    ExportFormat::Pdf => {
+                        ui.horizontal(|ui| {
+                            ui.label("PDF 引擎：");
+                            ui.text_edit_singleline(&mut profile.pdf_engine);
+                            egui::ComboBox::from_id_salt("pdf_engine_combo")
+                                .selected_text("常用引擎")
+                                .show_ui(ui, |ui| {
+                                    for engine in KNOWN_PDF_ENGINES {
+                                        ui.selectable_value(&mut profile.pdf_engine, engine.to_string(), engine);
+                                    }
+                                });
+                        });
+                        if profile.pdf_engine_is_latex() {
+                            ui.horizontal(|ui| {
+                                ui.label("中文字体：");
+                                ui.text_edit_singleline(&mut profile.pdf_cjk_font)
+                                    .on_hover_text("例如 SimSun、Noto Serif CJK SC，为空时使用引擎默认字体");
+                            });
+                        }
+                    }
+                }
+                ui.separator();
+
+                ui.checkbox(&mut profile.toc, "在文档开头生成目录")
+                    .on_hover_text("在文档中单独一行写 [TOC] 可以把目录放在该位置（DOCX 和 LaTeX 引擎的 PDF），此时总会生成目录");
+                ui.horizontal(|ui| {
+                    ui.label("目录级别：");
+                    ui.add(egui::DragValue::new(&mut profile.toc_depth).range(1..=6));
+                    ui.label("目录标题：");
+                    ui.add(egui::TextEdit::singleline(&mut profile.toc_title).desired_width(120.0));
+                });
+                ui.checkbox(&mut profile.number_sections, "为标题编号");
+                ui.horizontal(|ui| {
+                    ui.label("代码高亮：");
+                    let selected = if profile.highlight_style.is_empty() { "默认" } else { profile.highlight_style.as_str() };
+                    egui::ComboBox::from_id_salt("highlight_style_combo")
+                        .selected_text(selected.to_string())
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut profile.highlight_style, String::new(), "默认");
+                            for style in HIGHLIGHT_STYLES {
+                                ui.selectable_value(&mut profile.highlight_style, style.to_string(), style);
+                            }
+                        });
+                });
+
+                ui.collapsing("参考文献", |ui| {
+                    ui.horizontal(|ui| {
+                        let bibliography = profile.bibliography
+                            .as_ref()
+                            .map(|p| p.display().to_string())
+                            .unwrap_or_else(|| "（未设置，使用文档元数据中的 bibliography）".to_string());
+                        ui.label(format!("参考文献：{}", bibliography));
+                        if ui.button("选择...").clicked() {
+                            pick_bibliography = true;
+                        }
+                        if profile.bibliography.is_some() && ui.button("清除").clicked() {
+                            profile.bibliography = None;
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        let csl = profile.csl_style
+                            .as_ref()
+                            .map(|p| p.display().to_string())
+                            .unwrap_or_else(|| "（默认）".to_string());
+                        ui.label(format!("引用样式 (CSL)：{}", csl));
+                        if ui.button("选择...").clicked() {
+                            pick_csl = true;
+                        }
+                        if profile.csl_style.is_some() && ui.button("清除").clicked() {
+                            profile.csl_style = None;
+                        }
+                    });
+                });
+
+                ui.collapsing("元数据", |ui| {
+                    let mut remove_index = None;
+                    egui::Grid::new("export_metadata_grid").num_columns(3).show(ui, |ui| {
+                        for (index, (key, value)) in profile.metadata.iter_mut().enumerate() {
+                            ui.add(egui::TextEdit::singleline(key).hint_text("名称，如 author").desired_width(120.0));
+                            ui.add(egui::TextEdit::singleline(value).hint_text("值").desired_width(200.0));
+                            if ui.button("删除").clicked() {
+                                remove_index = Some(index);
+                            }
+                            ui.end_row();
+                        }
+                    });
+                    if let Some(index) = remove_index {
+                        profile.metadata.remove(index);
+                    }
+                    if ui.button("添加").clicked() {
+                        profile.metadata.push((String::new(), String::new()));
+                    }
+                });
+
+                ui.collapsing("过滤器", |ui| {
+                    let mut remove_index = None;
+                    let mut move_filter = None;
+                    let count = profile.filters.len();
+                    for (index, filter) in profile.filters.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut filter.enabled, export::filter_name(&filter.path))
+                                .on_hover_text(filter.path.display().to_string());
+                            if !filter.path.is_file() {
+                                ui.colored_label(ui.visuals().warn_fg_color, "⚠ 文件不存在");
+                            }
+                            if ui.add_enabled(index > 0, egui::Button::new("↑").small()).clicked() {
+                                move_filter = Some((index, index - 1));
+                            }
+                            if ui.add_enabled(index + 1 < count, egui::Button::new("↓").small()).clicked() {
+                                move_filter = Some((index, index + 1));
+                            }
+                            if ui.small_button("移除").clicked() {
+                                remove_index = Some(index);
+                            }
+                        });
+                    }
+                    if let Some((from, to)) = move_filter {
+                        profile.filters.swap(from, to);
+                    }
+                    if let Some(index) = remove_index {
+                        profile.filters.remove(index);
+                    }
+                    if profile.filters.is_empty() {
+                        ui.weak("此方案没有使用过滤器。过滤器按从上到下的顺序执行。");
+                    }
+
+                    ui.horizontal(|ui| {
+                        let available: Vec<&PathBuf> = filter_library.iter()
+                            .filter(|path| !profile.filters.iter().any(|filter| &filter.path == *path))
+                            .collect();
+                        ui.add_enabled_ui(!available.is_empty(), |ui| {
+                            ui.menu_button("添加已登记的过滤器", |ui| {
+                                for path in available {
+                                    if ui.button(export::filter_name(path)).on_hover_text(path.display().to_string()).clicked() {
+                                        profile.filters.push(FilterEntry { path: path.clone(), enabled: true });
+                                        ui.close();
+                                    }
+                                }
+                            });
+                        });
+                        if ui.button("添加文件...").clicked() {
+                            add_filter = true;
+                        }
+                        if ui.button("管理...").clicked() {
+                            open_filter_manager = true;
+                        }
+                    });
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("其他参数：");
+                    ui.text_edit_singleline(&mut profile.extra_args)
+                        .on_hover_text("追加到 pandoc 命令行的参数，例如 --shift-heading-level-by=1；包含空格的参数请用双引号括起");
+                });
+
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    if ui.button("导出...").clicked() {
+                        export_clicked = true;
+                    }
+                    if ui.button("取消").clicked() {
+                        cancel_clicked = true;
+                    }
+                });
+            });
+
+        if pick_reference {
+            let format = self.export_settings.active().format;
+            if let Some(path) = self.file_dialog()
+                .add_filter(format.label(), &[format.extension()])
+                .pick_file() {
+                self.export_settings.active_mut().reference_doc = Some(path);
+            }
+        }
+        if pick_css
+            && let Some(path) = self.file_dialog()
+                .add_filter("CSS 样式表", &["css"])
+                .pick_file() {
+            self.export_settings.active_mut().html_css = Some(path);
+        }
+        if pick_bibliography
+            && let Some(path) = self.file_dialog()
+                .add_filter("参考文献", &BIBLIOGRAPHY_EXTENSIONS)
+                .pick_file() {
+            self.export_settings.active_mut().bibliography = Some(path);
+        }
+        if pick_csl
+            && let Some(path) = self.file_dialog()
+                .add_filter("CSL 样式", &["csl"])
+                .pick_file() {
+            self.export_settings.active_mut().csl_style = Some(path);
+        }
+        if add_filter
+            && let Some(path) = self.pick_filter_file() {
+            self.export_settings.register_filter(path.clone());
+            let filters = &mut self.export_settings.active_mut().filters;
+            if !filters.iter().any(|filter| filter.path == path) {
+                filters.push(FilterEntry { path, enabled: true });
+            }
+        }
+        if open_filter_manager {
+            self.filter_manager_open = true;
+        }
+        if export_clicked {
+            self.export_dialog_open = false;
+            self.export_document(self.export_settings.active().clone());
+        }
+        if cancel_clicked {
+            self.export_dialog_open = false;
+        }
+    }
+
+    fn pick_filter_file(&self) -> Option<PathBuf> {
+        self.file_dialog()
+            .set_title("选择 pandoc 过滤器（Lua 脚本或可执行程序）")
+            .pick_file()
+    }
+
+    /// 登记和移除过滤器文件；登记后的过滤器可以在导出方案中启用和排序
+    pub fn show_filter_manager(&mut self, ctx: &egui::Context) {
+        let mut register_clicked = false;
+        let mut unregister = None;
+        let mut open_file = None;
+
+        egui::Window::new("过滤器管理")
+            .collapsible(false)
+            .resizable(true)
+            .default_width(420.0)
+            .open(&mut self.filter_manager_open)
+            .show(ctx, |ui| {
+                ui.label("登记常用的 pandoc 过滤器（例如分页、中文编号、表格样式），然后在“导出...”对话框中为每个导出方案启用并排序。");
+                ui.separator();
+
+                if self.export_settings.filter_library.is_empty() {
+                    ui.weak("还没有登记任何过滤器。");
+                }
+                egui::Grid::new("filter_library_grid").num_columns(3).striped(true).show(ui, |ui| {
+                    for path in &self.export_settings.filter_library {
+                        let kind = if export::is_lua_filter(path) { "Lua" } else { "程序" };
+                        ui.label(format!("{}（{}）", export::filter_name(path), kind))
+                            .on_hover_text(path.display().to_string());
+
+                        let used_by: Vec<&str> = self.export_settings.profiles.iter()
+                            .filter(|profile| profile.filters.iter().any(|filter| &filter.path == path))
+                            .map(|profile| profile.name.as_str())
+                            .collect();
+                        if !path.is_file() {
+                            ui.colored_label(ui.visuals().warn_fg_color, "⚠ 文件不存在");
+                        } else if used_by.is_empty() {
+                            ui.weak("未被使用");
+                        } else {
+                            ui.label(format!("用于：{}", used_by.join("、")));
+                        }
+
+                        ui.horizontal(|ui| {
+                            if path.is_file() && ui.small_button("打开").on_hover_text("在新标签页中查看或修改").clicked() {
+                                open_file = Some(path.clone());
+                            }
+                            if ui.small_button("移除").on_hover_text("同时从所有导出方案中移除").clicked() {
+                                unregister = Some(path.clone());
+                            }
+                        });
+                        ui.end_row();
+                    }
+                });
+
+                ui.add_space(10.0);
+                if ui.button("登记过滤器...").clicked() {
+                    register_clicked = true;
+                }
+            });
+
+        if register_clicked
+            && let Some(path) = self.pick_filter_file() {
+            self.export_settings.register_filter(path);
+        }
+        if let Some(path) = unregister {
+            self.export_settings.unregister_filter(&path);
+        }
+        if let Some(path) = open_file {
+            self.open_file_from_path(path);
+        }
+    }
+
+    /// 批量导出：选择数据表、输出文件夹和文件名模板，预览将要生成的文件
+    pub fn show_batch_export_dialog(&mut self, ctx: &egui::Context) {
+        let mut choose_table = false;
+        let mut choose_output_dir = false;
+        let mut export_clicked = false;
+        let mut cancel_clicked = false;
+
+        let profile_name = self.export_settings.active().name.clone();
+        let document_markers = &self.documents[self.active_document].template_markers;
+        let batch = &mut self.batch_export;
+
+        egui::Window::new("批量导出")
+            .collapsible(false)
+            .resizable(true)
+            .default_width(480.0)
+            .open(&mut self.batch_export_dialog_open)
+            .show(ctx, |ui| {
+                ui.label("数据表的第一行是变量名，之后每一行导出一份文档。编辑区中的文档不会被修改。");
+                ui.separator();
+
+                egui::Grid::new("batch_export_grid").num_columns(2).show(ui, |ui| {
+                    ui.label("数据表：");
+                    ui.horizontal(|ui| {
+                        match &batch.table_path {
+                            Some(path) => {
+                                let file_name = path.file_name()
+                                    .map(|s| s.to_string_lossy().into_owned())
+                                    .unwrap_or_default();
+                                ui.label(file_name).on_hover_text(path.display().to_string());
+                            }
+                            None => {
+                                ui.weak("未选择");
+                            }
+                        }
+                        if ui.button("选择...").on_hover_text("CSV、TSV 或 JSON 文件").clicked() {
+                            choose_table = true;
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("输出文件夹：");
+                    ui.horizontal(|ui| {
+                        match &batch.output_dir {
+                            Some(dir) => {
+                                ui.label(dir.display().to_string());
+                            }
+                            None => {
+                                ui.weak("未选择");
+                            }
+                        }
+                        if ui.button("选择...").clicked() {
+                            choose_output_dir = true;
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("格式：");
+                    egui::ComboBox::from_id_salt("batch_export_format_combo")
+                        .selected_text(batch.format.label())
+                        .show_ui(ui, |ui| {
+                            for format in ExportFormat::ALL {
+                                ui.selectable_value(&mut batch.format, format, format.label());
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("文件名：");
+                    ui.text_edit_singleline(&mut batch.file_name_pattern)
+                        .on_hover_text("可以使用数据表中的变量，例如 {{公司名称}}-投标书");
+                    ui.end_row();
+                });
+                ui.weak(format!("其他选项使用导出方案“{}”。", profile_name));
+
+                if let Some(table) = &batch.table {
+                    ui.separator();
+                    ui.label(format!("共 {} 行，变量：{}", table.rows.len(), table.columns.join("、")));
+
+                    let unmatched: Vec<&str> = document_markers.iter()
+                        .filter(|marker| !table.columns.contains(marker))
+                        .map(String::as_str)
+                        .collect();
+                    if !unmatched.is_empty() {
+                        ui.colored_label(
+                            ui.visuals().warn_fg_color,
+                            format!("⚠ 数据表中没有这些变量，导出后将保留原样：{}", unmatched.join("、")),
+                        );
+                    }
+                    let unused: Vec<&str> = table.columns.iter()
+                        .filter(|column| !document_markers.contains(column))
+                        .map(String::as_str)
+                        .collect();
+                    if !unused.is_empty() {
+                        ui.weak(format!("文档正文中没有使用：{}", unused.join("、")));
+                    }
+
+                    ui.add_space(5.0);
+                    ui.label("将生成以下文件：");
+                    let file_names = table.output_file_names(&batch.file_name_pattern, batch.format);
+                    egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                        for file_name in &file_names {
+                            let exists = batch.output_dir.as_ref().is_some_and(|dir| dir.join(file_name).exists());
+                            if exists {
+                                ui.colored_label(ui.visuals().warn_fg_color, format!("{}（已存在，将被覆盖）", file_name));
+                            } else {
+                                ui.label(file_name);
+                            }
+                        }
+                    });
+                }
+
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    let ready = batch.table.is_some() && batch.output_dir.is_some();
+                    let row_count = batch.table.as_ref().map_or(0, |table| table.rows.len());
+                    if ui.add_enabled(ready, egui::Button::new(format!("导出 {} 份", row_count))).clicked() {
+                        export_clicked = true;
+                    }
+                    if ui.button("取消").clicked() {
+                        cancel_clicked = true;
+                    }
+                });
+            });
+
+        if choose_table {
+            self.choose_batch_table();
+        }
+        if choose_output_dir {
+            self.choose_batch_output_dir();
+        }
+        if export_clicked {
+            self.run_batch_export();
+        }
+        if cancel_clicked {
+            self.batch_export_dialog_open = false;
+        }
+    }
+
+    pub fn show_import_options_dialog(&mut self, ctx: &egui::Context) {
+        let mut import_clicked = false;
+        let mut cancel_clicked = false;
+
+        let Some(pending) = &self.pending_import else {
+            self.import_options_dialog_open = false;
+            return;
+        };
+        let file_name = pending.input_path.file_name()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let is_docx = import::reader_for_path(&pending.input_path) == Some("docx");
+        let options = &mut self.import_options;
+
+        egui::Window::new("导入选项")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .open(&mut self.import_options_dialog_open)
+            .show(ctx, |ui| {
+                ui.label(format!("导入文件：{}", file_name));
+                ui.separator();
+
+                egui::Grid::new("import_options_grid").num_columns(2).show(ui, |ui| {
+                    ui.label("Markdown 风格：");
+                    egui::ComboBox::from_id_salt("import_flavor_combo")
+                        .selected_text(options.flavor.label())
+                        .show_ui(ui, |ui| {
+                            for flavor in MarkdownFlavor::ALL {
+                                ui.selectable_value(&mut options.flavor, flavor, flavor.label());
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("换行：");
+                    egui::ComboBox::from_id_salt("import_wrap_combo")
+                        .selected_text(options.wrap.label())
+                        .show_ui(ui, |ui| {
+                            for wrap in WrapMode::ALL {
+                                ui.selectable_value(&mut options.wrap, wrap, wrap.label());
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("表格：");
+                    ui.add_enabled_ui(options.flavor == MarkdownFlavor::Pandoc, |ui| {
+                        egui::ComboBox::from_id_salt("import_table_combo")
+                            .selected_text(options.table_style.label())
+                            .show_ui(ui, |ui| {
+                                for style in TableStyle::ALL {
+                                    ui.selectable_value(&mut options.table_style, style, style.label());
+                                }
+                            });
+                    })
+                    .response
+                    .on_disabled_hover_text("只有 Pandoc Markdown 可以选择表格形式");
+                    ui.end_row();
+
+                    ui.label("修订：");
+                    ui.add_enabled_ui(is_docx, |ui| {
+                        egui::ComboBox::from_id_salt("import_track_changes_combo")
+                            .selected_text(options.track_changes.label())
+                            .show_ui(ui, |ui| {
+                                for mode in TrackChanges::ALL {
+                                    ui.selectable_value(&mut options.track_changes, mode, mode.label());
+                                }
+                            });
+                    })
+                    .response
+                    .on_disabled_hover_text("仅适用于 Word 文档");
+                    ui.end_row();
+                });
+
+                ui.add_enabled(
+                    is_docx && options.flavor.supports_custom_styles(),
+                    egui::Checkbox::new(&mut options.preserve_styles, "保留 Word 样式（可在样式面板中继续使用）"),
+                )
+                .on_disabled_hover_text("仅适用于 Word 文档，且需要 Pandoc Markdown 或 CommonMark（含扩展）");
+
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    if ui.button("导入").clicked() {
+                        import_clicked = true;
+                    }
+                    if ui.button("取消").clicked() {
+                        cancel_clicked = true;
+                    }
+                });
+            });
+
+        if import_clicked {
+            self.import_options_dialog_open = false;
+            self.start_pending_import();
+        } else if cancel_clicked || !self.import_options_dialog_open {
+            self.import_options_dialog_open = false;
+            self.pending_import = None;
+        }
+    }
+
     pub fn show_import_dialog(&mut self, ctx: &egui::Context) {
         let mut import_and_close = false;
+        let mut import_file_clicked = false;
         let mut cancel_and_close = false;
 
         egui::Window::new("导入模板变量")
@@ -264,7 +1011,17 @@ impl MyApp {
             .default_width(400.0)
             .default_height(300.0)
             .show(ctx, |ui| {
-                ui.label("请将导出的变量内容粘贴到下方文本框中：");
+                ui.label("请将导出的变量内容粘贴到下方文本框中，或直接从文件导入：");
+                ui.horizontal(|ui| {
+                    ui.label("格式：");
+                    egui::ComboBox::from_id_salt("import_variables_format_combo")
+                        .selected_text(self.import_text_format.label())
+                        .show_ui(ui, |ui| {
+                            for format in VariableFormat::ALL {
+                                ui.selectable_value(&mut self.import_text_format, format, format.label());
+                            }
+                        });
+                });
                 ui.add_space(10.0);
 
                 egui::ScrollArea::vertical().show(ui, |ui| {
@@ -283,6 +1040,10 @@ impl MyApp {
                         import_and_close = true;
                     }
 
+                    if ui.button("从文件导入...").on_hover_text("支持 .txt（名称=值）、.json、.yaml 和 .csv 文件").clicked() {
+                        import_file_clicked = true;
+                    }
+
                     if ui.button("取消").clicked() {
                         cancel_and_close = true;
                     }
@@ -290,12 +1051,337 @@ impl MyApp {
             });
 
         if import_and_close {
-            self.import_and_apply_variables(&self.import_text_area.clone());
-            self.import_text_area.clear();
-            self.import_dialog_open = false;
+            // 解析失败时保留粘贴的内容，方便修改后重试
+            match self.import_and_apply_variables(&self.import_text_area.clone(), self.import_text_format) {
+                Ok(()) => {
+                    self.import_text_area.clear();
+                    self.import_dialog_open = false;
+                }
+                Err(error_message) => self.open_info_dialog("导入失败", &error_message),
+            }
+        }
+        if import_file_clicked {
+            self.import_variables_from_file();
         }
         if cancel_and_close {
             self.import_dialog_open = false;
         }
     }
+
+    pub fn show_unsaved_changes_dialog(&mut self, ctx: &egui::Context) {
+        let mut save_clicked = false;
+        let mut discard_clicked = false;
+        let mut cancel_clicked = false;
+
+        let dirty_indices = self.pending_action
+            .as_ref()
+            .map(|action| self.dirty_documents_for(action))
+            .unwrap_or_default();
+        let dirty_names: Vec<String> = dirty_indices
+            .iter()
+            .map(|&index| self.documents[index].display_name())
+            .collect();
+
+        egui::Window::new("未保存的修改")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .open(&mut self.unsaved_dialog_open)
+            .show(ctx, |ui| {
+                if let [name] = dirty_names.as_slice() {
+                    ui.label(format!("文档“{}”有未保存的修改，是否先保存？", name));
+                } else {
+                    ui.label("以下文档有未保存的修改，是否先保存？");
+                    for name in &dirty_names {
+                        ui.label(format!("• {}", name));
+                    }
+                }
+                ui.add_space(10.0);
+
+                ui.horizontal(|ui| {
+                    let save_label = if dirty_names.len() > 1 { "全部保存" } else { "保存" };
+                    if ui.button(save_label).clicked() {
+                        save_clicked = true;
+                    }
+                    if ui.button("不保存").clicked() {
+                        discard_clicked = true;
+                    }
+                    if ui.button("取消").clicked() {
+                        cancel_clicked = true;
+                    }
+                });
+            });
+
+        if save_clicked {
+            self.unsaved_dialog_open = false;
+            // 任一文档保存失败或用户取消了“另存为”，则放弃后续操作
+            let all_saved = dirty_indices.iter().all(|&index| self.save_document(index));
+            if all_saved
+                && let Some(action) = self.pending_action.take() {
+                self.perform_action(ctx, action);
+            }
+            self.pending_action = None;
+        }
+        if discard_clicked {
+            self.unsaved_dialog_open = false;
+            if let Some(action) = self.pending_action.take() {
+                self.perform_action(ctx, action);
+            }
+        }
+        if cancel_clicked || !self.unsaved_dialog_open {
+            self.unsaved_dialog_open = false;
+            self.pending_action = None;
+        }
+    }
+
+    /// 文档在外部被修改、而编辑器中也有未保存的修改时，让用户选择保留哪一份
+    pub fn show_external_change_dialog(&mut self, ctx: &egui::Context) {
+        let Some(index) = self.documents.iter().position(|d| d.changed_on_disk) else {
+            return;
+        };
+        let mut reload_clicked = false;
+        let mut keep_clicked = false;
+        let mut save_as_clicked = false;
+
+        let doc = &self.documents[index];
+        let name = doc.display_name();
+        let path = doc.file_path.as_ref().map(|p| p.display().to_string()).unwrap_or_default();
+
+        egui::Window::new("文件已在外部修改")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(format!("文档“{}”已被其他程序修改：", name));
+                ui.label(path);
+                ui.label("编辑器中也有尚未保存的修改，请选择要保留的内容。");
+                ui.add_space(10.0);
+
+                ui.horizontal(|ui| {
+                    if ui.button("重新加载").on_hover_text("放弃编辑器中的修改，使用磁盘上的新内容").clicked() {
+                        reload_clicked = true;
+                    }
+                    if ui.button("保留我的修改").on_hover_text("保存时将覆盖磁盘上的新内容").clicked() {
+                        keep_clicked = true;
+                    }
+                    if ui.button("另存为...").on_hover_text("将编辑器中的内容保存到其他文件").clicked() {
+                        save_as_clicked = true;
+                    }
+                });
+            });
+
+        if reload_clicked {
+            self.reload_document_from_disk(index);
+        }
+        if keep_clicked {
+            self.keep_local_changes(index);
+        }
+        if save_as_clicked {
+            self.save_document_as(index);
+        }
+    }
+
+    pub fn show_recovery_dialog(&mut self, ctx: &egui::Context) {
+        let mut restore_clicked = false;
+        let mut discard_clicked = false;
+
+        egui::Window::new("恢复未保存的内容")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .open(&mut self.recovery_dialog_open)
+            .show(ctx, |ui| {
+                let Some(snapshot) = &self.recovery_snapshot else {
+                    return;
+                };
+
+                ui.label("程序上次没有正常退出，发现一份自动保存的内容。");
+                ui.label(format!("保存时间: {}", snapshot.age_description()));
+                ui.label(format!(
+                    "共 {} 个文档，其中 {} 个有未保存的修改：",
+                    snapshot.documents.len(),
+                    snapshot.dirty_count()
+                ));
+                for saved in snapshot.documents.iter().filter(|d| d.is_dirty) {
+                    let name = saved.file_path
+                        .as_ref()
+                        .map(|p| p.display().to_string())
+                        .unwrap_or_else(|| "未命名".to_string());
+                    ui.label(format!("• {}（{} 字）", name, saved.markdown_text.chars().count()));
+                }
+                ui.add_space(10.0);
+
+                ui.horizontal(|ui| {
+                    if ui.button("恢复").clicked() {
+                        restore_clicked = true;
+                    }
+                    if ui.button("放弃").clicked() {
+                        discard_clicked = true;
+                    }
+                });
+            });
+
+        if restore_clicked {
+            self.restore_recovery_snapshot();
+            self.recovery_dialog_open = false;
+        } else if discard_clicked || !self.recovery_dialog_open {
+            self.recovery_dialog_open = false;
+            self.discard_recovery_snapshot();
+        }
+    }
+
+    pub fn show_merge_dialog(&mut self, ctx: &egui::Context) {
+        let mut merge_clicked = false;
+        let mut cancel_clicked = false;
+        let mut sort_clicked = false;
+        let mut moved = None;
+
+        egui::Window::new("合并文件")
+            .open(&mut self.merge_dialog_open)
+            .resizable(true)
+            .default_width(450.0)
+            .show(ctx, |ui| {
+                ui.label("拖动 ☰ 或使用箭头调整顺序，取消勾选的文件不会被合并：");
+                ui.add_space(5.0);
+
+                let entry_count = self.merge_entries.len();
+                egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    for (index, entry) in self.merge_entries.iter_mut().enumerate() {
+                        let row = ui.horizontal(|ui| {
+                            let handle_id = egui::Id::new(("merge_entry_handle", index));
+                            ui.dnd_drag_source(handle_id, index, |ui| {
+                                ui.label("☰");
+                            });
+
+                            let filename = entry.path.file_name()
+                                .map(|s| s.to_string_lossy().into_owned())
+                                .unwrap_or_default();
+                            ui.checkbox(&mut entry.include, format!("{}. {}", index + 1, filename))
+                                .on_hover_text(entry.path.to_string_lossy());
+
+                            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                if ui.add_enabled(index + 1 < entry_count, egui::Button::new("↓").small()).clicked() {
+                                    moved = Some((index, index + 1));
+                                }
+                                if ui.add_enabled(index > 0, egui::Button::new("↑").small()).clicked() {
+                                    moved = Some((index, index - 1));
+                                }
+                            });
+                        }).response;
+
+                        // 拖放到某一行时，将被拖动的文件移动到该位置
+                        if let Some(dragged) = row.dnd_hover_payload::<usize>()
+                            && *dragged != index {
+                            let y = if *dragged < index { row.rect.bottom() } else { row.rect.top() };
+                            ui.painter().hline(row.rect.x_range(), y, ui.visuals().selection.stroke);
+                        }
+                        if let Some(dragged) = row.dnd_release_payload::<usize>() {
+                            moved = Some((*dragged, index));
+                        }
+                    }
+                });
+
+                ui.add_space(5.0);
+                ui.horizontal(|ui| {
+                    ui.label("文件之间插入:");
+                    for separator in MergeSeparator::ALL {
+                        ui.radio_value(&mut self.merge_separator, separator, separator.label());
+                    }
+                });
+
+                ui.checkbox(&mut self.merge_insert_source_markers, "插入来源标记（之后可以拆分回原文件）");
+
+                ui.add_space(10.0);
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    if ui.button("合并").clicked() {
+                        merge_clicked = true;
+                    }
+                    if ui.button("按文件名排序").clicked() {
+                        sort_clicked = true;
+                    }
+                    if ui.button("取消").clicked() {
+                        cancel_clicked = true;
+                    }
+                });
+            });
+
+        if let Some((from, to)) = moved
+            && from < self.merge_entries.len()
+            && to < self.merge_entries.len() {
+            let entry = self.merge_entries.remove(from);
+            self.merge_entries.insert(to, entry);
+        }
+        if sort_clicked {
+            self.sort_merge_entries_by_name();
+        }
+        if merge_clicked {
+            self.merge_dialog_open = false;
+            self.perform_merge();
+        }
+        if cancel_clicked || !self.merge_dialog_open {
+            self.merge_dialog_open = false;
+            self.merge_entries.clear();
+        }
+    }
+
+    pub fn show_split_dialog(&mut self, ctx: &egui::Context) {
+        let mut confirm_clicked = false;
+        let mut cancel_clicked = false;
+        let changed_count = self.split_plan
+            .iter()
+            .filter(|s| s.status.will_write())
+            .count();
+
+        egui::Window::new("拆分回原文件")
+            .open(&mut self.split_dialog_open)
+            .resizable(true)
+            .default_width(450.0)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "当前文档包含 {} 个来源文件，其中 {} 个将被写回：",
+                    self.split_plan.len(),
+                    changed_count
+                ));
+                ui.add_space(5.0);
+
+                egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    egui::Grid::new("split_plan_grid").num_columns(2).striped(true).show(ui, |ui| {
+                        for segment in &self.split_plan {
+                            ui.label(segment.path.to_string_lossy());
+                            let status = egui::RichText::new(segment.status.label());
+                            let status = match segment.status {
+                                SplitStatus::Unchanged => status.weak(),
+                                SplitStatus::ChangedOnDisk => status.color(ui.visuals().warn_fg_color),
+                                _ => status.strong(),
+                            };
+                            ui.label(status);
+                            ui.end_row();
+                        }
+                    });
+                });
+
+                ui.add_space(10.0);
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(changed_count > 0, egui::Button::new("写回有变化的文件")).clicked() {
+                        confirm_clicked = true;
+                    }
+                    if ui.button("取消").clicked() {
+                        cancel_clicked = true;
+                    }
+                });
+            });
+
+        if confirm_clicked {
+            self.split_dialog_open = false;
+            self.perform_split_to_sources();
+        }
+        if cancel_clicked || !self.split_dialog_open {
+            self.split_dialog_open = false;
+            self.split_plan.clear();
+        }
+    }
 }
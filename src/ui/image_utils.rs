@@ -5,7 +5,7 @@ impl MyApp {
     /// 处理图片宽度控制功能
     /// 在选中的文本中为所有图片添加宽度控制代码
     pub fn apply_image_width_control(&mut self, ctx: &egui::Context) {
-        let editor_id = egui::Id::new("main_editor_id");
+        let editor_id = self.doc().editor_id();
         
         // 检查是否有选中的文本
         if let Some(mut state) = egui::TextEdit::load_state(ctx, editor_id)
@@ -16,11 +16,12 @@ impl MyApp {
                 let (start_char, end_char) = (primary_idx.min(secondary_idx), primary_idx.max(secondary_idx));
 
                 // 获取选中的文本
-                let char_to_byte: Vec<usize> = self.markdown_text.char_indices().map(|(i, _)| i).collect();
+                let doc = &mut self.documents[self.active_document];
+                let char_to_byte: Vec<usize> = doc.markdown_text.char_indices().map(|(i, _)| i).collect();
                 
                 if let Some(&start_byte) = char_to_byte.get(start_char) {
-                    let end_byte = char_to_byte.get(end_char).copied().unwrap_or(self.markdown_text.len());
-                    let selected_text = &self.markdown_text[start_byte..end_byte];
+                    let end_byte = char_to_byte.get(end_char).copied().unwrap_or(doc.markdown_text.len());
+                    let selected_text = &doc.markdown_text[start_byte..end_byte];
                     
                     // 查找选中文本中的所有图片标记
                     let image_regex = regex::Regex::new(r"\!\[(.*?)\]\((.*?)\)").unwrap();
@@ -43,7 +44,7 @@ impl MyApp {
                     
                     // 如果找到了图片，则更新选中的文本
                     if found_images {
-                        self.markdown_text.replace_range(start_byte..end_byte, &modified_text);
+                        doc.markdown_text.replace_range(start_byte..end_byte, &modified_text);
                         
                         // 更新光标位置到修改后文本的末尾
                         let new_text_char_len = modified_text.chars().count();
@@ -53,6 +54,7 @@ impl MyApp {
                             egui::text::CCursor::new(new_cursor_pos_char),
                         )));
                         state.store(ctx, editor_id);
+                        doc.is_dirty = true;
                     } else {
                         // 如果没有找到图片，显示提示信息
                         self.show_no_images_alert(ctx);
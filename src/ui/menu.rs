@@ -1,31 +1,75 @@
 use eframe::egui;
-use crate::state::MyApp;
+use crate::export::ExportFormat;
+use crate::state::{MyApp, PendingAction};
 
 impl MyApp {
     pub fn show_menu_bar(&mut self, ctx: &egui::Context) {
         egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
             egui::containers::menu::MenuBar::new().ui(ui, |ui| {
                 ui.menu_button("文件", |ui| {
+                    if ui.add(egui::Button::new("新建").shortcut_text("Ctrl+N")).clicked() {
+                        ui.close();
+                        self.new_document();
+                    }
                     if ui.button("打开").clicked() {
                         ui.close();
                         self.load_file();
                     }
-                    if ui.button("保存").clicked() {
+                    ui.menu_button("最近打开", |ui| {
+                        if let Some(path) = Self::show_recent_list(ui, &self.recent_files) {
+                            ui.close();
+                            self.open_file_from_path(path);
+                        }
+                        ui.separator();
+                        if ui.button("清除列表").clicked() {
+                            ui.close();
+                            self.recent_files.clear();
+                        }
+                    });
+                    if ui.add(egui::Button::new("保存").shortcut_text("Ctrl+S")).clicked() {
                         ui.close();
                         self.save_file();
                     }
+                    if ui.button("另存为...").clicked() {
+                        ui.close();
+                        self.save_file_as();
+                    }
+                    if ui.add(egui::Button::new("关闭标签页").shortcut_text("Ctrl+W")).clicked() {
+                        ui.close();
+                        let id = self.doc().id;
+                        self.request_action(ctx, PendingAction::CloseDocument(id));
+                    }
+                    ui.separator();
                     if ui.button("合并文件").clicked() {
                         ui.close();
                         self.merge_files();
                     }
+                    if ui.button("拆分回原文件...").clicked() {
+                        ui.close();
+                        self.prepare_split_to_sources();
+                    }
                     ui.separator();
-                    if ui.button("导入 DOCX...").clicked() {
+                    if ui.button("导入为新文档...").on_hover_text("支持 DOCX、ODT、HTML、RTF、LaTeX、纯文本等格式").clicked() {
                         ui.close();
-                        self.import_from_docx();
+                        self.import_file(ctx, false);
                     }
-                    if ui.button("导出为 DOCX").clicked() {
+                    if ui.button("导入到光标处...").clicked() {
                         ui.close();
-                        self.export_as_docx();
+                        self.import_file(ctx, true);
+                    }
+                    if ui.button("导出为 DOCX").on_hover_text("使用当前导出方案的选项导出为 DOCX").clicked() {
+                        ui.close();
+                        let mut profile = self.export_settings.active().clone();
+                        profile.format = ExportFormat::Docx;
+                        self.export_document(profile);
+                    }
+                    if ui.button("导出...").clicked() {
+                        ui.close();
+                        self.export_dialog_open = true;
+                    }
+                    if ui.button("批量导出...").on_hover_text("按数据表的每一行替换模板变量，分别导出一份文档").clicked() {
+                        ui.close();
+                        self.open_batch_export();
                     }
 
                     ui.separator();
@@ -34,6 +78,17 @@ impl MyApp {
                         ui.close();
                         self.set_reference_doc();
                     }
+                    ui.menu_button("最近模板", |ui| {
+                        if let Some(path) = Self::show_recent_list(ui, &self.recent_templates) {
+                            ui.close();
+                            self.use_reference_doc(path);
+                        }
+                        ui.separator();
+                        if ui.button("清除列表").clicked() {
+                            ui.close();
+                            self.recent_templates.clear();
+                        }
+                    });
 
                     let mut clear_template = false;
                     if let Some(path) = &self.reference_doc_path {
@@ -57,8 +112,56 @@ impl MyApp {
                     }
                 });
                 
+                ui.menu_button("项目", |ui| {
+                    if ui.button("新建项目...").clicked() {
+                        ui.close();
+                        self.new_project();
+                    }
+                    if ui.button("打开项目...").clicked() {
+                        ui.close();
+                        self.open_project();
+                    }
+
+                    let has_project = self.project.is_some();
+                    ui.add_enabled_ui(has_project, |ui| {
+                        if ui.button("保存项目").clicked() {
+                            ui.close();
+                            self.save_project();
+                        }
+                        if ui.button("关闭项目").clicked() {
+                            ui.close();
+                            self.close_project();
+                        }
+                        ui.separator();
+                        if ui.button("添加章节...").clicked() {
+                            ui.close();
+                            self.add_project_chapters();
+                        }
+                        if ui.button("使用当前导出模板").clicked() {
+                            ui.close();
+                            self.set_project_reference_doc_from_current();
+                        }
+                        if ui.button("设置变量文件...").clicked() {
+                            ui.close();
+                            self.set_project_variables_file();
+                        }
+                        ui.separator();
+                        if ui.button("导出项目").clicked() {
+                            ui.close();
+                            self.export_project(false);
+                        }
+                        if ui.button("导出项目为...").on_hover_text("选择新的导出位置，之后导出项目时使用").clicked() {
+                            ui.close();
+                            self.export_project(true);
+                        }
+                    });
+                });
+
                 ui.menu_button("视图", |ui| {
                     ui.checkbox(&mut self.scroll_linked, "同步滚动");
+                    ui.add_enabled(self.project.is_some(), egui::Checkbox::new(&mut self.project_panel_open, "项目侧栏"));
+                    ui.checkbox(&mut self.log_panel_open, "Pandoc 日志");
+                    ui.checkbox(&mut self.metadata_panel_open, "文档属性");
                 });
                 
                 ui.menu_button("工具", |ui| {
@@ -70,6 +173,14 @@ impl MyApp {
                         self.apply_underline_to_variables(ctx);
                         ui.close();
                     }
+                    if ui.add(egui::Button::new("插入引用...").shortcut_text("Ctrl+Shift+R")).clicked() {
+                        self.open_citation_picker();
+                        ui.close();
+                    }
+                    if ui.button("插入目录标记").on_hover_text("导出时在光标所在行之前生成目录").clicked() {
+                        self.insert_toc_marker(ctx);
+                        ui.close();
+                    }
                     ui.separator();
                     if ui.button("导入模板变量").clicked() {
                         self.import_dialog_open = true;
@@ -79,6 +190,19 @@ impl MyApp {
                         self.export_template_variables();
                         ui.close();
                     }
+                    ui.separator();
+                    if ui.button("过滤器管理...").clicked() {
+                        self.filter_manager_open = true;
+                        ui.close();
+                    }
+                    if ui.button("设置 Pandoc 路径...").clicked() {
+                        self.choose_pandoc_path();
+                        ui.close();
+                    }
+                    if self.custom_pandoc_path.is_some() && ui.button("自动查找 Pandoc").clicked() {
+                        self.set_pandoc_path(None);
+                        ui.close();
+                    }
                 });
 
                 ui.menu_button("帮助", |ui| {
@@ -90,4 +214,30 @@ impl MyApp {
             });
         });
     }
+
+    /// 渲染最近文件列表，返回被点击的路径
+    fn show_recent_list(ui: &mut egui::Ui, paths: &[std::path::PathBuf]) -> Option<std::path::PathBuf> {
+        if paths.is_empty() {
+            ui.label("（无）");
+            return None;
+        }
+
+        let mut clicked = None;
+        for path in paths {
+            let filename = path.file_name()
+                .map(|s| s.to_string_lossy())
+                .unwrap_or_default();
+            let parent = path.parent()
+                .and_then(|p| p.file_name())
+                .map(|s| s.to_string_lossy())
+                .unwrap_or_default();
+
+            if ui.button(format!("{}  ({})", filename, parent))
+                .on_hover_text(path.to_string_lossy())
+                .clicked() {
+                clicked = Some(path.clone());
+            }
+        }
+        clicked
+    }
 }
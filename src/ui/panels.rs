@@ -1,9 +1,14 @@
 use eframe::egui;
-use crate::state::MyApp;
+use crate::preview::PreviewCache;
+use crate::citations::CitationIndex;
+use crate::metadata::{CustomField, CustomValue, MetadataEditor};
+use crate::pandoc_log::DiagnosticLevel;
+use crate::state::{MyApp, PendingAction};
 
 impl MyApp {
     pub fn apply_formatting_to_selection(&mut self, ctx: &egui::Context, prefix: &str, suffix: &str) {
-        let editor_id = egui::Id::new("main_editor_id");
+        let editor_id = self.doc().editor_id();
+        let doc = self.doc_mut();
         if let Some(mut state) = egui::TextEdit::load_state(ctx, editor_id)
             && let Some(char_range) = state.cursor.char_range() {
             let (primary_idx, secondary_idx) = (char_range.primary.index, char_range.secondary.index);
@@ -11,13 +16,13 @@ impl MyApp {
             if primary_idx != secondary_idx {
                 let (start_char, end_char) = (primary_idx.min(secondary_idx), primary_idx.max(secondary_idx));
 
-                let char_to_byte: Vec<usize> = self.markdown_text.char_indices().map(|(i, _)| i).collect();
+                let char_to_byte: Vec<usize> = doc.markdown_text.char_indices().map(|(i, _)| i).collect();
                 
                 if let Some(&start_byte) = char_to_byte.get(start_char) {
-                    let end_byte = char_to_byte.get(end_char).copied().unwrap_or(self.markdown_text.len());
+                    let end_byte = char_to_byte.get(end_char).copied().unwrap_or(doc.markdown_text.len());
 
-                    let new_text = format!("{}{}{}", prefix, &self.markdown_text[start_byte..end_byte], suffix);
-                    self.markdown_text.replace_range(start_byte..end_byte, &new_text);
+                    let new_text = format!("{}{}{}", prefix, &doc.markdown_text[start_byte..end_byte], suffix);
+                    doc.markdown_text.replace_range(start_byte..end_byte, &new_text);
 
                     let new_text_char_len = new_text.chars().count();
                     let new_cursor_pos_char = start_char + new_text_char_len;
@@ -26,6 +31,7 @@ impl MyApp {
                         egui::text::CCursor::new(new_cursor_pos_char),
                     )));
                     state.store(ctx, editor_id);
+                    doc.is_dirty = true;
                 }
             }
         }
@@ -33,19 +39,28 @@ impl MyApp {
 
     pub fn show_panels(&mut self, ctx: &egui::Context) {
         egui::CentralPanel::default().show(ctx, |ui| {
+            self.show_tab_strip(ctx, ui);
+            ui.add_space(4.0);
+
+            let scroll_linked = self.scroll_linked;
+            let profile_bibliography = self.export_settings.active().bibliography.clone();
+            let keep_template_markers = self.keep_template_markers;
+            let active = self.active_document;
+            let doc = &mut self.documents[active];
+            let editor_id = doc.editor_id();
             let stroke_color = ui.style().visuals.widgets.noninteractive.bg_stroke.color;
             
             // 先计算编辑区的滚动信息
             let (editor_content_height, editor_visible_height) = {
                 let font_id = egui::TextStyle::Monospace.resolve(ui.style());
                 let char_width = ui.fonts(|f| f.glyph_width(&font_id, '0'));
-                let line_count = self.markdown_text.lines().count().max(1);
+                let line_count = doc.markdown_text.lines().count().max(1);
                 let num_digits = line_count.to_string().len();
                 let line_number_width = (num_digits as f32 * char_width) + 15.0;
                 
                 let galley = {
                     let mut job = egui::text::LayoutJob::default();
-                    job.append(&self.markdown_text, 0.0, egui::TextFormat::simple(font_id.clone(), ui.style().visuals.text_color()));
+                    job.append(&doc.markdown_text, 0.0, egui::TextFormat::simple(font_id.clone(), ui.style().visuals.text_color()));
                     job.wrap.max_width = ui.available_width() / 2.0 - line_number_width; // 估算宽度
                     ui.fonts(|f| f.layout_job(job))
                 };
@@ -75,13 +90,13 @@ impl MyApp {
                                     ui.with_layout(egui::Layout::top_down(egui::Align::Min), |ui| {
                                         let font_id = egui::TextStyle::Monospace.resolve(ui.style());
                                         let char_width = ui.fonts(|f| f.glyph_width(&font_id, '0'));
-                                        let line_count = self.markdown_text.lines().count().max(1);
+                                        let line_count = doc.markdown_text.lines().count().max(1);
                                         let num_digits = line_count.to_string().len();
                                         let line_number_width = (num_digits as f32 * char_width) + 15.0;
 
                                         let galley = {
                                             let mut job = egui::text::LayoutJob::default();
-                                            job.append(&self.markdown_text, 0.0, egui::TextFormat::simple(font_id.clone(), ui.style().visuals.text_color()));
+                                            job.append(&doc.markdown_text, 0.0, egui::TextFormat::simple(font_id.clone(), ui.style().visuals.text_color()));
                                             job.wrap.max_width = ui.available_width() - line_number_width;
                                             ui.fonts(|f| f.layout_job(job))
                                         };
@@ -119,19 +134,34 @@ impl MyApp {
                                             };
                                             ui.scope(line_number_painter);
 
-                                            egui::TextEdit::multiline(&mut self.markdown_text)
-                                                .id(egui::Id::new("main_editor_id"))
+                                            let mut editor_output = egui::TextEdit::multiline(&mut doc.markdown_text)
+                                                .id(editor_id)
                                                 .code_editor()
                                                 .desired_width(ui.available_width() - line_number_width)
                                                 .desired_rows(1)
-                                                .show(ui)
-                                                .response
+                                                .show(ui);
+
+                                            if editor_output.response.changed() {
+                                                doc.is_dirty = true;
+                                            }
+
+                                            // 从其他位置（例如日志面板）请求的光标跳转
+                                            if let Some(char_index) = doc.pending_cursor.take() {
+                                                let cursor = egui::text::CCursor::new(char_index);
+                                                editor_output.state.cursor.set_char_range(Some(egui::text::CCursorRange::one(cursor)));
+                                                editor_output.state.store(ui.ctx(), editor_id);
+                                                editor_output.response.request_focus();
+                                                let cursor_rect = editor_output.galley
+                                                    .pos_from_cursor(cursor)
+                                                    .translate(editor_output.galley_pos.to_vec2());
+                                                ui.scroll_to_rect(cursor_rect, Some(egui::Align::Center));
+                                            }
                                         });
                                     });
                                 });
 
                             if editor_scrollable {
-                                self.scroll_proportion = editor_scroll_response.state.offset.y / max_offset_y;
+                                doc.scroll_proportion = editor_scroll_response.state.offset.y / max_offset_y;
                             }
 
                         });
@@ -145,12 +175,50 @@ impl MyApp {
                             ui.label("预览区:");
                             ui.add_space(5.0);
 
+                            // 文档中有包含指令或相对路径的图片时，预览处理后的内容
+                            let base_dir = doc.base_dir().map(|dir| dir.to_path_buf());
+
+                            if let Some(index) = CitationIndex::refresh(
+                                &mut doc.citation_index,
+                                &doc.markdown_text,
+                                base_dir.as_deref(),
+                                profile_bibliography.as_deref(),
+                            ) {
+                                for error in &index.errors {
+                                    ui.colored_label(ui.visuals().warn_fg_color, format!("⚠ {}", error));
+                                }
+                                for key in &index.missing {
+                                    ui.colored_label(ui.visuals().warn_fg_color, format!("⚠ 参考文献中没有 @{}", key));
+                                }
+                            }
+
+                            // 保留占位符时，预览显示替换为已赋值之后的内容
+                            let variables = (keep_template_markers && doc.marker_values.values().any(|v| !v.is_empty()))
+                                .then_some(&doc.marker_values);
+
+                            let preview_text: &str = if variables.is_some()
+                                || PreviewCache::needs_preparation(&doc.markdown_text, base_dir.as_deref()) {
+                                let expanded = PreviewCache::refresh(
+                                    &mut doc.preview_cache,
+                                    &doc.markdown_text,
+                                    variables,
+                                    doc.file_path.as_deref(),
+                                    base_dir.as_deref(),
+                                );
+                                for error in &expanded.errors {
+                                    ui.colored_label(ui.visuals().warn_fg_color, format!("⚠ {}", error));
+                                }
+                                &expanded.text
+                            } else {
+                                &doc.markdown_text
+                            };
+
                             let mut preview_scroll_area = egui::ScrollArea::vertical()
                                 .id_salt("preview_scroll_area")
                                 .auto_shrink([false; 2]);
 
-                            if self.scroll_linked && editor_scrollable {
-                                let target_offset_y = self.scroll_proportion * self.preview_max_scroll;
+                            if scroll_linked && editor_scrollable {
+                                let target_offset_y = doc.scroll_proportion * doc.preview_max_scroll;
                                 preview_scroll_area = preview_scroll_area.vertical_scroll_offset(target_offset_y);
                             } else if !editor_scrollable {
                                 preview_scroll_area = preview_scroll_area.vertical_scroll_offset(0.0);
@@ -160,14 +228,483 @@ impl MyApp {
                                 egui::Frame::NONE
                                     .inner_margin(egui::Margin::same(10))
                                     .show(ui, |ui| {
-                                        egui_commonmark::CommonMarkViewer::new().show(ui, &mut self.cache, &self.markdown_text);
+                                        egui_commonmark::CommonMarkViewer::new()
+                                            .render_html_fn(Some(&render_preview_html))
+                                            .show(ui, &mut doc.cache, preview_text);
                                     });
                             });
 
-                            self.preview_max_scroll = preview_scroll_response.content_size.y - preview_scroll_response.inner_rect.height();
+                            doc.preview_max_scroll = preview_scroll_response.content_size.y - preview_scroll_response.inner_rect.height();
                         });
                     });
             });
         });
     }
+
+    /// 渲染编辑区上方的标签页栏
+    fn show_tab_strip(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        let mut activate = None;
+        let mut close = None;
+        let mut move_by = None;
+
+        egui::ScrollArea::horizontal()
+            .id_salt("tab_strip_scroll_area")
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    for (index, doc) in self.documents.iter().enumerate() {
+                        let marker = if doc.is_dirty { "*" } else { "" };
+                        let label = format!("{}{}", doc.display_name(), marker);
+                        let is_active = index == self.active_document;
+
+                        let response = ui.selectable_label(is_active, label);
+                        let response = match &doc.file_path {
+                            Some(path) => response.on_hover_text(path.to_string_lossy()),
+                            None => response,
+                        };
+                        if response.clicked() {
+                            activate = Some(index);
+                        }
+                        response.context_menu(|ui| {
+                            if ui.button("左移").clicked() {
+                                activate = Some(index);
+                                move_by = Some(-1);
+                                ui.close();
+                            }
+                            if ui.button("右移").clicked() {
+                                activate = Some(index);
+                                move_by = Some(1);
+                                ui.close();
+                            }
+                            ui.separator();
+                            if ui.button("关闭").clicked() {
+                                close = Some(doc.id);
+                                ui.close();
+                            }
+                        });
+
+                        if ui.small_button("×").on_hover_text("关闭 (Ctrl+W)").clicked() {
+                            close = Some(doc.id);
+                        }
+                        ui.separator();
+                    }
+
+                    if ui.small_button("+").on_hover_text("新建 (Ctrl+N)").clicked() {
+                        self.new_document();
+                    }
+                });
+            });
+
+        if let Some(index) = activate {
+            self.active_document = index;
+        }
+        if let Some(offset) = move_by {
+            self.move_active_document(offset);
+        }
+        if let Some(id) = close {
+            self.request_action(ctx, PendingAction::CloseDocument(id));
+        }
+    }
+
+    /// 渲染底部状态栏：显示正在执行的 pandoc 任务、排队的任务和最近的结果
+    pub fn show_status_bar(&mut self, ctx: &egui::Context) {
+        let mut cancel_running = false;
+        let mut remove_queued = None;
+
+        egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if let Some((description, elapsed)) = self.jobs.running() {
+                    ui.spinner();
+                    ui.label(format!("{}（{} 秒）", description, elapsed.as_secs()));
+                    if ui.small_button("取消").clicked() {
+                        cancel_running = true;
+                    }
+                } else if let Some(message) = &self.job_status_message {
+                    ui.weak(message);
+                }
+
+                let queued: Vec<(u64, String)> = self.jobs
+                    .queued()
+                    .map(|(id, description)| (id, description.to_owned()))
+                    .collect();
+                if !queued.is_empty() {
+                    ui.separator();
+                    ui.menu_button(format!("排队中 {} 个任务", queued.len()), |ui| {
+                        for (id, description) in &queued {
+                            ui.horizontal(|ui| {
+                                ui.label(description);
+                                if ui.small_button("×").on_hover_text("从队列中移除").clicked() {
+                                    remove_queued = Some(*id);
+                                }
+                            });
+                        }
+                    });
+                }
+
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    ui.toggle_value(&mut self.log_panel_open, "Pandoc 日志");
+                });
+            });
+        });
+
+        if cancel_running {
+            self.jobs.cancel_running();
+        }
+        if let Some(id) = remove_queued {
+            self.jobs.remove_queued(id);
+        }
+    }
+
+    /// 在光标处插入文本，有选中的内容时替换选中的内容
+    pub fn insert_at_cursor(&mut self, ctx: &egui::Context, text: &str) {
+        let editor_id = self.doc().editor_id();
+        let doc = self.doc_mut();
+        let text_len = doc.markdown_text.chars().count();
+        let (start_char, end_char) = egui::TextEdit::load_state(ctx, editor_id)
+            .and_then(|state| state.cursor.char_range())
+            .map(|range| {
+                let (a, b) = (range.primary.index, range.secondary.index);
+                (a.min(b), a.max(b))
+            })
+            .unwrap_or((text_len, text_len));
+
+        let byte_index = |char_index: usize| {
+            doc.markdown_text
+                .char_indices()
+                .nth(char_index)
+                .map(|(i, _)| i)
+                .unwrap_or(doc.markdown_text.len())
+        };
+        let (start_byte, end_byte) = (byte_index(start_char), byte_index(end_char));
+        doc.markdown_text.replace_range(start_byte..end_byte, text);
+        doc.is_dirty = true;
+        doc.pending_cursor = Some(start_char + text.chars().count());
+    }
+
+    /// 渲染底部的 Pandoc 日志面板，列出每次调用的命令行、耗时、退出码和输出的警告
+    pub fn show_log_panel(&mut self, ctx: &egui::Context) {
+        let mut jump_to = None;
+        let mut open_filter = None;
+        let mut clear_clicked = false;
+        let mut close_clicked = false;
+
+        egui::TopBottomPanel::bottom("pandoc_log_panel")
+            .resizable(true)
+            .default_height(180.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.strong("Pandoc 日志");
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        if ui.small_button("×").on_hover_text("关闭日志面板").clicked() {
+                            close_clicked = true;
+                        }
+                        if ui.small_button("清空").clicked() {
+                            clear_clicked = true;
+                        }
+                    });
+                });
+                ui.separator();
+
+                if self.pandoc_log.is_empty() {
+                    ui.weak("还没有执行过 pandoc 任务。");
+                    return;
+                }
+
+                egui::ScrollArea::vertical()
+                    .id_salt("pandoc_log_scroll_area")
+                    .auto_shrink([false; 2])
+                    .show(ui, |ui| {
+                        let latest = self.pandoc_log.len() - 1;
+                        for (index, entry) in self.pandoc_log.iter().enumerate().rev() {
+                            let exit_code = entry.exit_code
+                                .map(|code| code.to_string())
+                                .unwrap_or_else(|| "无".to_string());
+                            let title = format!(
+                                "[{}] {}（{:.1} 秒，退出码 {}，{} 条信息）",
+                                entry.status.label(),
+                                entry.description,
+                                entry.duration.as_secs_f32(),
+                                exit_code,
+                                entry.diagnostics.len()
+                            );
+                            egui::CollapsingHeader::new(title)
+                                .id_salt(("pandoc_log_entry", index))
+                                .default_open(index == latest && entry.has_problems())
+                                .show(ui, |ui| {
+                                    ui.label("命令行：");
+                                    ui.add(egui::Label::new(egui::RichText::new(&entry.command_line).monospace()).selectable(true));
+
+                                    for diagnostic in &entry.diagnostics {
+                                        ui.horizontal(|ui| {
+                                            let color = match diagnostic.level {
+                                                DiagnosticLevel::Info => ui.visuals().weak_text_color(),
+                                                DiagnosticLevel::Warning => ui.visuals().warn_fg_color,
+                                                DiagnosticLevel::Error => ui.visuals().error_fg_color,
+                                            };
+                                            ui.colored_label(color, format!("[{}]", diagnostic.level.label()));
+                                            if let (Some(line), Some(document_id)) = (diagnostic.line, entry.source_document)
+                                                && ui.link(format!("第 {} 行", line)).clicked() {
+                                                jump_to = Some((document_id, line));
+                                            }
+                                            if let Some(filter) = &diagnostic.filter {
+                                                let name = filter.path.file_name()
+                                                    .map(|s| s.to_string_lossy().into_owned())
+                                                    .unwrap_or_else(|| filter.path.display().to_string());
+                                                let label = match filter.line {
+                                                    Some(line) => format!("{} 第 {} 行", name, line),
+                                                    None => name,
+                                                };
+                                                if ui.link(label).on_hover_text("打开过滤器文件").clicked() {
+                                                    open_filter = Some(filter.clone());
+                                                }
+                                            }
+                                            ui.add(egui::Label::new(&diagnostic.message).wrap());
+                                        });
+                                    }
+
+                                    if !entry.stderr.is_empty() {
+                                        egui::CollapsingHeader::new("完整输出")
+                                            .id_salt(("pandoc_log_stderr", index))
+                                            .show(ui, |ui| {
+                                                ui.add(egui::Label::new(egui::RichText::new(&entry.stderr).monospace()).selectable(true));
+                                            });
+                                    }
+                                });
+                        }
+                    });
+            });
+
+        if let Some((document_id, line)) = jump_to {
+            self.jump_to_line(document_id, line);
+        }
+        if let Some(filter) = open_filter {
+            self.open_filter_location(&filter);
+        }
+        if clear_clicked {
+            self.pandoc_log.clear();
+        }
+        if close_clicked {
+            self.log_panel_open = false;
+        }
+    }
+
+    /// 渲染右侧的文档属性面板，编辑当前文档开头的 YAML 元数据
+    pub fn show_metadata_panel(&mut self, ctx: &egui::Context) {
+        let mut jump_to = None;
+        let mut close_clicked = false;
+
+        let active = self.active_document;
+        let doc = &mut self.documents[active];
+        let editor = MetadataEditor::sync(&mut self.metadata_editor, doc);
+        let mut changed = false;
+
+        egui::SidePanel::right("metadata_panel")
+            .resizable(true)
+            .default_width(260.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.strong("文档属性");
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        if ui.small_button("×").on_hover_text("关闭文档属性面板").clicked() {
+                            close_clicked = true;
+                        }
+                    });
+                });
+                ui.separator();
+
+                let form = match &mut editor.form {
+                    Ok(form) => form,
+                    Err(error) => {
+                        ui.colored_label(ui.visuals().error_fg_color, "文档开头的 YAML 元数据有语法错误：");
+                        ui.add(egui::Label::new(error.to_string()).wrap());
+                        if let Some(line) = error.line
+                            && ui.link("跳转到错误位置").clicked() {
+                            jump_to = Some((doc.id, line));
+                        }
+                        ui.weak("请在编辑区中修正后继续。");
+                        return;
+                    }
+                };
+
+                egui::ScrollArea::vertical()
+                    .id_salt("metadata_scroll_area")
+                    .auto_shrink([false; 2])
+                    .show(ui, |ui| {
+                        ui.label("标题");
+                        changed |= ui.text_edit_singleline(&mut form.title).changed();
+                        ui.label("副标题");
+                        changed |= ui.text_edit_singleline(&mut form.subtitle).changed();
+
+                        ui.label("作者");
+                        let mut remove_author = None;
+                        for (index, author) in form.authors.iter_mut().enumerate() {
+                            ui.horizontal(|ui| {
+                                changed |= ui.text_edit_singleline(author).changed();
+                                if ui.small_button("删除").clicked() {
+                                    remove_author = Some(index);
+                                }
+                            });
+                        }
+                        if let Some(index) = remove_author {
+                            form.authors.remove(index);
+                            changed = true;
+                        }
+                        if ui.small_button("添加作者").clicked() {
+                            form.authors.push(String::new());
+                        }
+
+                        ui.label("日期");
+                        changed |= ui.text_edit_singleline(&mut form.date).changed();
+                        ui.label("摘要");
+                        changed |= ui.add(egui::TextEdit::multiline(&mut form.abstract_text).desired_rows(3)).changed();
+                        ui.label("关键词");
+                        changed |= ui.add(egui::TextEdit::singleline(&mut form.keywords).hint_text("用逗号分隔")).changed();
+
+                        ui.separator();
+                        ui.label("自定义字段");
+                        let mut remove_field = None;
+                        for (index, field) in form.custom.iter_mut().enumerate() {
+                            ui.horizontal(|ui| {
+                                changed |= ui.add(egui::TextEdit::singleline(&mut field.key).hint_text("名称").desired_width(80.0)).changed();
+                                match &mut field.value {
+                                    CustomValue::Text(text) => {
+                                        changed |= ui.add(egui::TextEdit::singleline(text).hint_text("值").desired_width(110.0)).changed();
+                                    }
+                                    CustomValue::Structured(_) => {
+                                        ui.weak("（复杂的值，请在编辑区中修改）");
+                                    }
+                                }
+                                if ui.small_button("删除").clicked() {
+                                    remove_field = Some(index);
+                                }
+                            });
+                        }
+                        if let Some(index) = remove_field {
+                            form.custom.remove(index);
+                            changed = true;
+                        }
+                        if ui.small_button("添加字段").clicked() {
+                            form.custom.push(CustomField { key: String::new(), value: CustomValue::Text(String::new()) });
+                        }
+                    });
+            });
+
+        if changed {
+            editor.write_back(doc);
+        }
+        if let Some((document_id, line)) = jump_to {
+            self.jump_to_line(document_id, line);
+        }
+        if close_clicked {
+            self.metadata_panel_open = false;
+        }
+    }
+
+    /// 渲染左侧的项目侧栏，按顺序列出项目的章节
+    pub fn show_project_panel(&mut self, ctx: &egui::Context) {
+        let mut open_chapter = None;
+        let mut move_chapter = None;
+        let mut remove_chapter = None;
+        let mut add_chapters = false;
+        let mut export_project = None;
+
+        let active_path = self.doc().file_path.clone();
+        let Some(project) = &self.project else {
+            return;
+        };
+
+        egui::SidePanel::left("project_panel")
+            .resizable(true)
+            .default_width(220.0)
+            .show(ctx, |ui| {
+                let marker = if project.is_dirty { "*" } else { "" };
+                ui.heading(format!("{}{}", project.manifest.name, marker));
+                ui.label(egui::RichText::new(project.manifest_path.to_string_lossy()).small().weak());
+                if let Some(reference_doc) = &project.manifest.reference_doc {
+                    ui.label(format!("模板: {}", reference_doc.display()));
+                }
+                if let Some(variables_file) = &project.manifest.variables_file {
+                    ui.label(format!("变量文件: {}", variables_file.display()));
+                }
+                if let Some(output_path) = &project.manifest.export.output_path {
+                    ui.label(format!("导出到: {}", output_path.display()));
+                }
+                ui.separator();
+
+                ui.label("章节:");
+                egui::ScrollArea::vertical()
+                    .id_salt("project_chapters_scroll_area")
+                    .auto_shrink([false, true])
+                    .max_height(ui.available_height() - 60.0)
+                    .show(ui, |ui| {
+                        let chapter_count = project.manifest.chapters.len();
+                        for (index, chapter) in project.manifest.chapters.iter().enumerate() {
+                            let full_path = project.resolve(chapter);
+                            let is_active = active_path.as_ref() == Some(&full_path);
+                            let exists = full_path.is_file();
+
+                            ui.horizontal(|ui| {
+                                let mut text = egui::RichText::new(format!("{}. {}", index + 1, chapter.display()));
+                                if !exists {
+                                    text = text.strikethrough().weak();
+                                }
+                                let response = ui.selectable_label(is_active, text)
+                                    .on_hover_text(full_path.to_string_lossy());
+                                if response.clicked() && exists {
+                                    open_chapter = Some(full_path.clone());
+                                }
+
+                                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                    if ui.small_button("×").on_hover_text("从项目中移除").clicked() {
+                                        remove_chapter = Some(index);
+                                    }
+                                    if ui.add_enabled(index + 1 < chapter_count, egui::Button::new("↓").small()).clicked() {
+                                        move_chapter = Some((index, 1));
+                                    }
+                                    if ui.add_enabled(index > 0, egui::Button::new("↑").small()).clicked() {
+                                        move_chapter = Some((index, -1));
+                                    }
+                                });
+                            });
+                        }
+                    });
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button("添加章节...").clicked() {
+                        add_chapters = true;
+                    }
+                    if ui.button("导出项目").clicked() {
+                        export_project = Some(false);
+                    }
+                    if ui.button("导出为...").on_hover_text("选择新的导出位置").clicked() {
+                        export_project = Some(true);
+                    }
+                });
+            });
+
+        if let Some(path) = open_chapter {
+            self.open_file_from_path(path);
+        }
+        if let Some(project) = &mut self.project {
+            if let Some((index, offset)) = move_chapter {
+                project.move_chapter(index, offset);
+            }
+            if let Some(index) = remove_chapter {
+                project.remove_chapter(index);
+            }
+        }
+        if add_chapters {
+            self.add_project_chapters();
+        }
+        if let Some(choose_output) = export_project {
+            self.export_project(choose_output);
+        }
+    }
+}
+
+/// 预览区中 HTML 块的渲染方式：隐藏合并时插入的来源标记，其余 HTML 按原文显示
+fn render_preview_html(ui: &mut egui::Ui, html: &str) {
+    if !crate::file_handler::is_source_marker(html) {
+        ui.label(egui::RichText::new(html.trim_end()).monospace());
+    }
 }
@@ -0,0 +1,251 @@
+use crate::app::{marker_for_key, parse_variable_assignments};
+use crate::batch::parse_delimited;
+use crate::metadata;
+use crate::state::MyApp;
+use serde_yaml_ng::{Mapping, Value};
+use std::path::Path;
+
+/// 模板变量文件的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VariableFormat {
+    /// 每行一个 `{{名称}}=值`，值中不能有换行
+    #[default]
+    KeyValue,
+    Json,
+    Yaml,
+    /// 两列：变量名和值，第一行是表头
+    Csv,
+}
+
+impl VariableFormat {
+    pub const ALL: [VariableFormat; 4] = [
+        VariableFormat::KeyValue,
+        VariableFormat::Json,
+        VariableFormat::Yaml,
+        VariableFormat::Csv,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            VariableFormat::KeyValue => "名称=值（每行一个）",
+            VariableFormat::Json => "JSON",
+            VariableFormat::Yaml => "YAML",
+            VariableFormat::Csv => "CSV",
+        }
+    }
+
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            VariableFormat::KeyValue => &["txt"],
+            VariableFormat::Json => &["json"],
+            VariableFormat::Yaml => &["yaml", "yml"],
+            VariableFormat::Csv => &["csv"],
+        }
+    }
+
+    /// 按扩展名判断格式，无法识别的扩展名按 `名称=值` 格式处理
+    pub fn from_path(path: &Path) -> Self {
+        Self::from_extension(path).unwrap_or_default()
+    }
+
+    /// 按扩展名判断格式，无法识别时返回 None
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        VariableFormat::ALL
+            .into_iter()
+            .find(|format| format.extensions().contains(&extension.as_str()))
+    }
+
+    /// 解析变量文本，返回 `({{名称}}, 值)` 列表，顺序与文本中一致
+    pub fn parse(&self, text: &str) -> Result<Vec<(String, String)>, String> {
+        let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+        match self {
+            VariableFormat::KeyValue => Ok(parse_variable_assignments(text)),
+            VariableFormat::Json => {
+                let json: serde_json::Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+                let value = serde_yaml_ng::to_value(json).map_err(|e| e.to_string())?;
+                mapping_assignments(&value)
+            }
+            VariableFormat::Yaml => {
+                let value: Value = serde_yaml_ng::from_str(text).map_err(|e| e.to_string())?;
+                mapping_assignments(&value)
+            }
+            VariableFormat::Csv => Ok(parse_delimited(text, ',')
+                .into_iter()
+                .skip(1)
+                .filter(|record| !record[0].trim().is_empty())
+                .map(|record| {
+                    let value = record.get(1).cloned().unwrap_or_default();
+                    (marker_for_key(&record[0]), value)
+                })
+                .collect()),
+        }
+    }
+
+    /// 按给定顺序写出变量。JSON、YAML 和 CSV 中的变量名不带 `{{ }}`，值可以包含换行。
+    pub fn format(&self, variables: &[(String, String)]) -> String {
+        match self {
+            VariableFormat::KeyValue => variables.iter()
+                .map(|(marker, value)| format!("{}={}\n", marker, value))
+                .collect(),
+            VariableFormat::Json => {
+                // 手工拼接以保持变量的顺序
+                let entries: Vec<String> = variables.iter()
+                    .map(|(marker, value)| {
+                        format!(
+                            "  {}: {}",
+                            serde_json::Value::from(key_of(marker)),
+                            serde_json::Value::from(value.as_str())
+                        )
+                    })
+                    .collect();
+                if entries.is_empty() {
+                    "{}\n".to_string()
+                } else {
+                    format!("{{\n{}\n}}\n", entries.join(",\n"))
+                }
+            }
+            VariableFormat::Yaml => {
+                let mut mapping = Mapping::new();
+                for (marker, value) in variables {
+                    mapping.insert(key_of(marker).into(), Value::String(value.clone()));
+                }
+                serde_yaml_ng::to_string(&Value::Mapping(mapping)).unwrap_or_default()
+            }
+            VariableFormat::Csv => {
+                let mut csv = String::from("变量,值\r\n");
+                for (marker, value) in variables {
+                    csv.push_str(&format!("{},{}\r\n", csv_field(key_of(marker)), csv_field(value)));
+                }
+                csv
+            }
+        }
+    }
+}
+
+/// `{{名称}}` 中的名称
+fn key_of(marker: &str) -> &str {
+    marker.strip_prefix("{{")
+        .and_then(|key| key.strip_suffix("}}"))
+        .unwrap_or(marker)
+}
+
+/// 含有逗号、双引号或换行的字段用双引号包围
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// 读取“名称: 值”映射中的变量；值可以是文本、数字或布尔值
+fn mapping_assignments(value: &Value) -> Result<Vec<(String, String)>, String> {
+    let Some(mapping) = value.as_mapping() else {
+        return Err("变量必须写成“名称: 值”形式的映射。".to_string());
+    };
+    let mut assignments = Vec::new();
+    for (key, value) in mapping {
+        let key = scalar_text(key).ok_or("变量名必须是文本。")?;
+        let value = scalar_text(value).ok_or_else(|| format!("变量 {} 的值必须是文本、数字或布尔值。", key))?;
+        assignments.push((marker_for_key(&key), value));
+    }
+    Ok(assignments)
+}
+
+/// 空值视为空文本，其余与元数据中的文本值相同
+fn scalar_text(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => Some(String::new()),
+        other => metadata::scalar_text(other),
+    }
+}
+
+/// 按扩展名读取变量文件
+pub fn load_variables_file(path: &Path) -> Result<Vec<(String, String)>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("无法读取变量文件 {}：{}", path.display(), e))?;
+    VariableFormat::from_path(path)
+        .parse(&text)
+        .map_err(|e| format!("无法解析变量文件 {}：{}", path.display(), e))
+}
+
+/// 所有变量文件格式的扩展名，用于文件对话框
+pub fn all_extensions() -> Vec<&'static str> {
+    VariableFormat::ALL.iter().flat_map(|format| format.extensions()).copied().collect()
+}
+
+impl MyApp {
+    /// 从文件中导入模板变量
+    pub fn import_variables_from_file(&mut self) {
+        let Some(path) = self.file_dialog()
+            .add_filter("变量文件", &all_extensions())
+            .pick_file() else {
+            return;
+        };
+        self.remember_directory(&path);
+        match load_variables_file(&path) {
+            Ok(assignments) => {
+                self.import_dialog_open = false;
+                self.apply_imported_variables(assignments);
+            }
+            Err(error_message) => self.open_info_dialog("导入失败", &error_message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<(String, String)> {
+        vec![
+            ("{{公司名称}}".to_string(), "甲公司, \"总部\"".to_string()),
+            ("{{地址}}".to_string(), "北京市\n朝阳区".to_string()),
+            ("{{编号}}".to_string(), String::new()),
+        ]
+    }
+
+    #[test]
+    fn multiline_values_round_trip() {
+        for format in [VariableFormat::Json, VariableFormat::Yaml, VariableFormat::Csv] {
+            let text = format.format(&sample());
+            assert_eq!(format.parse(&text).unwrap(), sample(), "{}", format.label());
+        }
+    }
+
+    #[test]
+    fn key_value_round_trip() {
+        let variables = vec![
+            ("{{名称}}".to_string(), "a=b".to_string()),
+            ("{{日期}}".to_string(), "2024年1月1日".to_string()),
+        ];
+        let text = VariableFormat::KeyValue.format(&variables);
+        assert_eq!(VariableFormat::KeyValue.parse(&text).unwrap(), variables);
+    }
+
+    #[test]
+    fn parses_scalars_and_rejects_nested_values() {
+        let parsed = VariableFormat::Yaml.parse("数量: 3\n启用: true\n备注:\n").unwrap();
+        assert_eq!(parsed, vec![
+            ("{{数量}}".to_string(), "3".to_string()),
+            ("{{启用}}".to_string(), "true".to_string()),
+            ("{{备注}}".to_string(), String::new()),
+        ]);
+        assert!(VariableFormat::Json.parse("{\"列表\": [1, 2]}").is_err());
+        assert!(VariableFormat::Json.parse("[1, 2]").is_err());
+    }
+
+    #[test]
+    fn csv_skips_header_and_strips_bom() {
+        let parsed = VariableFormat::Csv.parse("\u{feff}变量,值\r\n姓名,张三\r\n,忽略\r\n").unwrap();
+        assert_eq!(parsed, vec![("{{姓名}}".to_string(), "张三".to_string())]);
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(VariableFormat::from_extension(Path::new("a.YML")), Some(VariableFormat::Yaml));
+        assert_eq!(VariableFormat::from_extension(Path::new("variables")), None);
+        assert_eq!(VariableFormat::from_path(Path::new("a.vars")), VariableFormat::KeyValue);
+    }
+}
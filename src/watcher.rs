@@ -0,0 +1,105 @@
+use crate::project::Project;
+use crate::state::MyApp;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+/// 检查已打开文件是否在外部被修改的时间间隔
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// 读取文件在磁盘上的修改时间，文件不存在或无法访问时返回 None
+pub fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl MyApp {
+    /// 定期检查已打开的文档和项目清单是否被其他程序修改。
+    /// 没有本地修改的文档直接重新加载；有本地修改的文档标记为冲突，由用户决定如何处理。
+    pub fn check_external_changes(&mut self, ctx: &egui::Context) {
+        // 即使用户停止操作也要按时触发下一次检查
+        ctx.request_repaint_after(WATCH_INTERVAL);
+
+        if self.last_watch_check.elapsed() < WATCH_INTERVAL {
+            return;
+        }
+        self.last_watch_check = Instant::now();
+
+        for index in 0..self.documents.len() {
+            self.detect_external_change(index);
+        }
+
+        if let Some(project) = &self.project
+            && !project.is_dirty
+            && modified_time(&project.manifest_path) != project.disk_modified
+            && let Ok(reloaded) = Project::load(project.manifest_path.clone()) {
+            self.project = Some(reloaded);
+        }
+    }
+
+    /// 检查一个文档对应的文件是否在外部被修改，返回该文档是否存在尚未处理的冲突。
+    /// 内容与编辑器中相同的修改（例如只是重新保存）会被忽略。
+    pub fn detect_external_change(&mut self, index: usize) -> bool {
+        let doc = &mut self.documents[index];
+        if doc.changed_on_disk {
+            return true;
+        }
+        let Some(path) = &doc.file_path else {
+            return false;
+        };
+
+        let disk_modified = modified_time(path);
+        if disk_modified == doc.disk_modified {
+            return false;
+        }
+        let Some(disk_modified) = disk_modified else {
+            // 文件被删除或移走：编辑器中的内容成为唯一的副本
+            doc.disk_modified = None;
+            doc.is_dirty = true;
+            return false;
+        };
+        // 文件可能正在被写入，读取失败时留到下次检查
+        let Ok(content) = std::fs::read_to_string(path) else {
+            return false;
+        };
+
+        if content == doc.markdown_text {
+            doc.disk_modified = Some(disk_modified);
+            false
+        } else if doc.is_dirty {
+            doc.changed_on_disk = true;
+            true
+        } else {
+            doc.markdown_text = content;
+            doc.disk_modified = Some(disk_modified);
+            doc.preview_cache = None;
+            false
+        }
+    }
+
+    /// 放弃编辑器中的修改，使用磁盘上的新内容
+    pub fn reload_document_from_disk(&mut self, index: usize) {
+        let Some(path) = self.documents[index].file_path.clone() else {
+            return;
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(content) => {
+                let doc = &mut self.documents[index];
+                doc.markdown_text = content;
+                doc.disk_modified = modified_time(&path);
+                doc.is_dirty = false;
+                doc.changed_on_disk = false;
+                doc.preview_cache = None;
+            }
+            Err(e) => {
+                self.documents[index].changed_on_disk = false;
+                self.open_info_dialog("重新加载失败", &format!("无法读取文件 {}：{}", path.display(), e));
+            }
+        }
+    }
+
+    /// 保留编辑器中的修改；之后保存时将覆盖磁盘上的新内容
+    pub fn keep_local_changes(&mut self, index: usize) {
+        let doc = &mut self.documents[index];
+        doc.disk_modified = doc.file_path.as_deref().and_then(modified_time);
+        doc.changed_on_disk = false;
+    }
+}